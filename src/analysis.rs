@@ -0,0 +1,116 @@
+use std::f32::consts::PI;
+
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+// Value at `time` seconds, linearly interpolated between samples and silent past either end
+pub fn sample_at(samples: &[f32], sample_rate: u32, time: f32) -> f32 {
+    let position = time as f64 * sample_rate as f64;
+    if position < 0.0 {
+        return 0.0;
+    }
+    let i = position as usize;
+    let fraction = position.fract() as f32;
+    let at = |i: usize| samples.get(i).copied().unwrap_or(0.0);
+    at(i) + (at(i + 1) - at(i)) * fraction
+}
+
+// Index and value of the sample furthest from zero
+pub fn peak(samples: &[f32]) -> Option<(usize, f32)> {
+    samples
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+}
+
+// In-place iterative radix-2 FFT. `re` and `im` must have the same power of two length.
+pub fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+// Short-time Fourier transform using a Hann window. Each frame holds the
+// magnitudes of the first `window / 2` bins.
+pub fn stft(samples: &[f32], window: usize, hop: usize) -> Vec<Vec<f32>> {
+    let hann: Vec<f32> = (0..window)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / window as f32).cos())
+        .collect();
+
+    let mut frames = Vec::new();
+    let mut start = 0;
+    while start < samples.len() {
+        let mut re: Vec<f32> = (0..window)
+            .map(|i| samples.get(start + i).copied().unwrap_or(0.0) * hann[i])
+            .collect();
+        let mut im = vec![0.0; window];
+        fft(&mut re, &mut im);
+
+        frames.push(
+            (0..window / 2)
+                .map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt())
+                .collect(),
+        );
+        start += hop;
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SineWave, A4_FREQ, DEFAULT_AMPLITUDE};
+
+    #[test]
+    fn sample_at_reads_a_rendered_sine() {
+        let samples: Vec<f32> = SineWave::new(A4_FREQ, 1.0).collect();
+        let sample_rate = 44100;
+
+        // a quarter of a cycle in is the peak, between two samples
+        let peak = sample_at(&samples, sample_rate, 0.25 / A4_FREQ);
+        assert!((peak - DEFAULT_AMPLITUDE).abs() < 1e-3, "peak {}", peak);
+
+        let between = sample_at(&samples, sample_rate, 10.5 / sample_rate as f32);
+        assert!((between - (samples[10] + samples[11]) / 2.0).abs() < 1e-6);
+        assert!((sample_at(&samples, sample_rate, 10.0 / sample_rate as f32) - samples[10]).abs() < 1e-6);
+
+        assert_eq!(sample_at(&samples, sample_rate, 2.0), 0.0);
+        assert_eq!(sample_at(&samples, sample_rate, -1.0), 0.0);
+    }
+}
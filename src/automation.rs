@@ -0,0 +1,77 @@
+use crate::effects::{Biquad, BUTTERWORTH_Q};
+use crate::SAMPLE_RATE;
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    // Gain multiplier
+    Volume,
+    // Lowpass cutoff in Hz, the filter is only applied when a cutoff lane exists
+    Cutoff,
+    Pan,
+}
+
+impl Target {
+    pub const ALL: [Target; 3] = [Target::Volume, Target::Cutoff, Target::Pan];
+
+    // Matches the serde name
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Volume => "volume",
+            Target::Cutoff => "cutoff",
+            Target::Pan => "pan",
+        }
+    }
+}
+
+// Breakpoints of [song time in seconds, value], linearly interpolated and held past either end
+#[derive(Deserialize, Clone)]
+pub struct Lane {
+    pub target: Target,
+    pub points: Vec<[f32; 2]>,
+}
+
+// Samples between cutoff changes, the coefficients are too costly to recompute every sample
+pub const CUTOFF_UPDATE_INTERVAL: usize = 32;
+
+impl Lane {
+    pub fn value_at(&self, time: f32) -> f32 {
+        let after = self.points.iter().position(|point| point[0] > time);
+        match after {
+            Some(0) => self.points[0][1],
+            Some(i) => {
+                let [t0, v0] = self.points[i - 1];
+                let [t1, v1] = self.points[i];
+                v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+            }
+            None => self.points.last().map_or(0.0, |point| point[1]),
+        }
+    }
+}
+
+pub fn apply(lanes: &[Lane], samples: &mut [f32], high_precision: bool) {
+    for lane in lanes {
+        if lane.points.is_empty() {
+            continue;
+        }
+        match lane.target {
+            Target::Volume => {
+                for (i, sample) in samples.iter_mut().enumerate() {
+                    *sample *= lane.value_at(i as f32 / SAMPLE_RATE);
+                }
+            }
+            Target::Cutoff => {
+                let mut filter = Biquad::lowpass(lane.value_at(0.0), BUTTERWORTH_Q)
+                    .with_high_precision(high_precision);
+                for (i, sample) in samples.iter_mut().enumerate() {
+                    if i % CUTOFF_UPDATE_INTERVAL == 0 {
+                        filter.set_lowpass(lane.value_at(i as f32 / SAMPLE_RATE), BUTTERWORTH_Q);
+                    }
+                    *sample = filter.process(*sample);
+                }
+            }
+            Target::Pan => eprintln!("Warning: ignoring pan automation, output is mono"),
+        }
+    }
+}
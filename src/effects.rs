@@ -0,0 +1,407 @@
+// Post-render passes over the final sample buffer.
+
+use crate::SAMPLE_RATE;
+use serde::Deserialize;
+use std::f32::consts::PI;
+
+// Coefficient for a one-pole smoother that covers ~63% of a step in `ms`
+fn smoothing_coefficient(ms: f32) -> f32 {
+    if ms <= 0.0 {
+        return 0.0;
+    }
+    (-1.0 / (ms / 1000.0 * SAMPLE_RATE)).exp()
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(1e-9).log10()
+}
+
+pub fn from_db(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+// Feed-forward compressor with a peak level detector.
+pub struct Compressor {
+    threshold_db: f32,
+    ratio: f32,
+    attack: f32,
+    release: f32,
+}
+
+impl Compressor {
+    pub fn new(threshold_db: f32, ratio: f32, attack_ms: f32, release_ms: f32) -> Self {
+        Self {
+            threshold_db,
+            ratio: ratio.max(1.0),
+            attack: smoothing_coefficient(attack_ms),
+            release: smoothing_coefficient(release_ms),
+        }
+    }
+
+    pub fn process(&self, samples: &mut [f32]) {
+        // Gain reduction in dB, smoothed so it rises with the attack time and falls with the release time
+        let mut reduction = 0.0;
+
+        for sample in samples.iter_mut() {
+            let over = to_db(sample.abs()) - self.threshold_db;
+            let target = if over > 0.0 { over * (1.0 - 1.0 / self.ratio) } else { 0.0 };
+
+            let coefficient = if target > reduction { self.attack } else { self.release };
+            reduction = coefficient * reduction + (1.0 - coefficient) * target;
+
+            *sample *= from_db(-reduction);
+        }
+    }
+}
+
+// Flattest passband without a resonant peak
+pub const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+// Transposed direct form II biquad, coefficients from the RBJ audio EQ cookbook. Everything is
+// held in f64, but only --high-precision runs the recursion at that precision, otherwise it is
+// rounded to f32 on every sample.
+#[derive(Default)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+    high_precision: bool,
+}
+
+// Angular frequency of `freq` in radians per sample, kept off Nyquist
+fn angular(freq: f32) -> f64 {
+    2.0 * std::f64::consts::PI * freq.clamp(1.0, SAMPLE_RATE * 0.49) as f64 / SAMPLE_RATE as f64
+}
+
+impl Biquad {
+    pub fn lowpass(cutoff: f32, q: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_lowpass(cutoff, q);
+        filter
+    }
+
+    // Changes the coefficients while keeping the filter state, so the cutoff can be swept
+    pub fn set_lowpass(&mut self, cutoff: f32, q: f32) {
+        let w0 = angular(cutoff);
+        let alpha = w0.sin() / (2.0 * q as f64);
+        let cos_w0 = w0.cos();
+        let a0 = 1.0 + alpha;
+
+        self.b0 = (1.0 - cos_w0) / 2.0 / a0;
+        self.b1 = (1.0 - cos_w0) / a0;
+        self.b2 = self.b0;
+        self.a1 = -2.0 * cos_w0 / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+
+    // Unity gain at `center`, falling away either side
+    pub fn bandpass(center: f32, q: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_bandpass(center, q);
+        filter
+    }
+
+    // Like set_lowpass, keeps the filter state
+    pub fn set_bandpass(&mut self, center: f32, q: f32) {
+        let w0 = angular(center);
+        let alpha = w0.sin() / (2.0 * q as f64);
+        let a0 = 1.0 + alpha;
+
+        self.b0 = alpha / a0;
+        self.b1 = 0.0;
+        self.b2 = -alpha / a0;
+        self.a1 = -2.0 * w0.cos() / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+
+    // Gain at `freq` in dB, from the transfer function evaluated on the unit circle
+    pub fn response_db(&self, freq: f32) -> f32 {
+        let w = 2.0 * std::f64::consts::PI * freq as f64 / SAMPLE_RATE as f64;
+        let (sin1, cos1) = (-w).sin_cos();
+        let (sin2, cos2) = (-2.0 * w).sin_cos();
+
+        let numerator_re = self.b0 + self.b1 * cos1 + self.b2 * cos2;
+        let numerator_im = self.b1 * sin1 + self.b2 * sin2;
+        let denominator_re = 1.0 + self.a1 * cos1 + self.a2 * cos2;
+        let denominator_im = self.a1 * sin1 + self.a2 * sin2;
+
+        let magnitude = (numerator_re.hypot(numerator_im)) / denominator_re.hypot(denominator_im);
+        to_db(magnitude as f32)
+    }
+
+    // Run the filter in f64 rather than f32
+    pub fn with_high_precision(mut self, high_precision: bool) -> Self {
+        self.high_precision = high_precision;
+        self
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        if self.high_precision {
+            let input = input as f64;
+            let output = self.b0 * input + self.z1;
+            self.z1 = self.b1 * input - self.a1 * output + self.z2;
+            self.z2 = self.b2 * input - self.a2 * output;
+            return output as f32;
+        }
+        let [b0, b1, b2, a1, a2] = [self.b0, self.b1, self.b2, self.a1, self.a2].map(|c| c as f32);
+        let output = b0 * input + self.z1 as f32;
+        self.z1 = (b1 * input - a1 * output + self.z2 as f32) as f64;
+        self.z2 = (b2 * input - a2 * output) as f64;
+        output
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Vowel {
+    Ah,
+    Eh,
+    Ee,
+    Oh,
+    Oo,
+}
+
+impl Vowel {
+    pub const ALL: [Vowel; 5] = [Vowel::Ah, Vowel::Eh, Vowel::Ee, Vowel::Oh, Vowel::Oo];
+
+    // Matches the serde name
+    pub fn name(self) -> &'static str {
+        match self {
+            Vowel::Ah => "ah",
+            Vowel::Eh => "eh",
+            Vowel::Ee => "ee",
+            Vowel::Oh => "oh",
+            Vowel::Oo => "oo",
+        }
+    }
+
+    // First three formant frequencies of an adult male voice, after Peterson and Barney
+    fn formants(self) -> [f32; 3] {
+        match self {
+            Vowel::Ah => [730.0, 1090.0, 2440.0],
+            Vowel::Eh => [530.0, 1840.0, 2480.0],
+            Vowel::Ee => [270.0, 2290.0, 3010.0],
+            Vowel::Oh => [570.0, 840.0, 2410.0],
+            Vowel::Oo => [300.0, 870.0, 2240.0],
+        }
+    }
+}
+
+const FORMANT_Q: f32 = 5.0;
+// Higher formants are weaker
+const FORMANT_GAINS: [f32; 3] = [1.0, 0.5, 0.25];
+
+// Parallel band-passes at a vowel's formants, summed.
+pub struct Formant {
+    filters: Vec<(Biquad, f32)>,
+}
+
+impl Formant {
+    pub fn new(vowel: Vowel) -> Self {
+        Self {
+            filters: Formant::bands(vowel)
+                .zip(FORMANT_GAINS)
+                .map(|((_, filter), gain)| (filter, gain))
+                .collect(),
+        }
+    }
+
+    // Center frequency and band-pass of each formant, lowest first
+    pub fn bands(vowel: Vowel) -> impl Iterator<Item = (f32, Biquad)> {
+        vowel.formants().into_iter().map(|center| (center, Biquad::bandpass(center, FORMANT_Q)))
+    }
+
+    // Retunes the bands to another vowel, keeping their state
+    pub fn set_vowel(&mut self, vowel: Vowel) {
+        for ((filter, _), center) in self.filters.iter_mut().zip(vowel.formants()) {
+            filter.set_bandpass(center, FORMANT_Q);
+        }
+    }
+
+    pub fn with_high_precision(mut self, high_precision: bool) -> Self {
+        self.filters = self
+            .filters
+            .into_iter()
+            .map(|(filter, gain)| (filter.with_high_precision(high_precision), gain))
+            .collect();
+        self
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            let input = *sample;
+            *sample = self.filters.iter_mut().map(|(filter, gain)| filter.process(input) * *gain).sum();
+        }
+    }
+}
+
+// Tracks the running RMS level of a signal.
+pub struct EnvelopeFollower {
+    coefficient: f32,
+    mean_square: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(window_ms: f32) -> Self {
+        Self {
+            coefficient: smoothing_coefficient(window_ms),
+            mean_square: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.mean_square = self.coefficient * self.mean_square + (1.0 - self.coefficient) * input * input;
+        self.mean_square.sqrt()
+    }
+}
+
+// Resonant lowpass whose cutoff opens as the input gets louder.
+pub struct AutoWah {
+    sensitivity: f32,
+    min_cutoff: f32,
+    max_cutoff: f32,
+    high_precision: bool,
+}
+
+pub const AUTOWAH_Q: f32 = 4.0;
+const AUTOWAH_WINDOW_MS: f32 = 15.0;
+// Recomputing the coefficients every sample is wasted work, the envelope moves much slower
+const AUTOWAH_UPDATE_INTERVAL: usize = 32;
+
+impl AutoWah {
+    pub fn new(sensitivity: f32, min_cutoff: f32, max_cutoff: f32) -> Self {
+        Self {
+            sensitivity,
+            min_cutoff,
+            max_cutoff,
+            high_precision: false,
+        }
+    }
+
+    pub fn with_high_precision(mut self, high_precision: bool) -> Self {
+        self.high_precision = high_precision;
+        self
+    }
+
+    pub fn process(&self, samples: &mut [f32]) {
+        let mut follower = EnvelopeFollower::new(AUTOWAH_WINDOW_MS);
+        let mut filter = Biquad::lowpass(self.min_cutoff, AUTOWAH_Q).with_high_precision(self.high_precision);
+
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let envelope = follower.process(*sample);
+            if i % AUTOWAH_UPDATE_INTERVAL == 0 {
+                let position = (envelope * self.sensitivity).min(1.0);
+                // sweep exponentially so the movement sounds even across the range
+                let cutoff = self.min_cutoff * (self.max_cutoff / self.min_cutoff).powf(position);
+                filter.set_lowpass(cutoff, AUTOWAH_Q);
+            }
+            *sample = filter.process(*sample);
+        }
+    }
+}
+
+// Dry signal mixed with a copy delayed by a few ms, the delay swept by a sine LFO.
+pub struct Flanger {
+    rate: f32,
+    depth: f32,
+    feedback: f32,
+}
+
+// Shortest delay, so the sweep never quite reaches the dry signal
+const FLANGER_BASE_MS: f32 = 0.5;
+// Beyond this the feedback loop rings on indefinitely
+const FLANGER_MAX_FEEDBACK: f32 = 0.95;
+
+impl Flanger {
+    pub fn new(rate_hz: f32, depth_ms: f32, feedback: f32) -> Self {
+        Self {
+            rate: rate_hz,
+            depth: depth_ms.max(0.0) / 1000.0 * SAMPLE_RATE,
+            feedback: feedback.clamp(-FLANGER_MAX_FEEDBACK, FLANGER_MAX_FEEDBACK),
+        }
+    }
+
+    pub fn process(&self, samples: &mut [f32]) {
+        let base = FLANGER_BASE_MS / 1000.0 * SAMPLE_RATE;
+        let mut line = vec![0.0; (base + self.depth) as usize + 2];
+        let mut write = 0;
+
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let sweep = 0.5 - 0.5 * (2.0 * PI * self.rate * i as f32 / SAMPLE_RATE).cos();
+            let delay = base + self.depth * sweep;
+
+            // linear interpolation between the two samples either side of the delay
+            let position = write as f32 + line.len() as f32 - delay;
+            let j = position as usize % line.len();
+            let fraction = position.fract();
+            let delayed = line[j] + (line[(j + 1) % line.len()] - line[j]) * fraction;
+
+            // scaling the input keeps the resonances of the feedback loop at unity gain
+            line[write] = *sample * (1.0 - self.feedback.abs()) + delayed * self.feedback;
+            write = (write + 1) % line.len();
+            *sample = 0.5 * (*sample + delayed);
+        }
+    }
+}
+
+// Brickwall limiter. The whole buffer is available, so instead of delaying the
+// signal it reads ahead by `lookahead` samples and has the gain fully down by
+// the time a peak arrives.
+pub struct Limiter {
+    ceiling: f32,
+    lookahead: usize,
+    release: f32,
+}
+
+impl Limiter {
+    pub fn new(ceiling_db: f32, lookahead_ms: f32, release_ms: f32, sample_rate: u32) -> Self {
+        let to_samples = |ms: f32| (ms / 1000.0 * sample_rate as f32) as usize;
+        Self {
+            ceiling: from_db(ceiling_db),
+            lookahead: to_samples(lookahead_ms).max(1),
+            release: (-1.0 / to_samples(release_ms).max(1) as f32).exp(),
+        }
+    }
+
+    pub fn process(&self, samples: &mut [f32]) {
+        let required: Vec<f32> = samples
+            .iter()
+            .map(|sample| (self.ceiling / sample.abs()).min(1.0))
+            .collect();
+
+        // Lowest gain needed anywhere in the next `lookahead` samples
+        let mut ahead = vec![1.0; samples.len()];
+        let mut window = std::collections::VecDeque::new();
+        for i in (0..samples.len()).rev() {
+            while window.back().is_some_and(|&j: &usize| required[j] >= required[i]) {
+                window.pop_back();
+            }
+            window.push_back(i);
+            if window[0] > i + self.lookahead {
+                window.pop_front();
+            }
+            ahead[i] = required[window[0]];
+        }
+
+        // Averaging the previous `lookahead` values ramps the gain down smoothly. Every value in
+        // the average looked ahead past the current sample, so it never exceeds what it needs.
+        let mut sum = 0.0_f64;
+        let mut gain = 1.0_f32;
+        for i in 0..samples.len() {
+            sum += ahead[i] as f64;
+            if i > self.lookahead {
+                sum -= ahead[i - self.lookahead - 1] as f64;
+            }
+            let count = (i + 1).min(self.lookahead + 1);
+            let ramp = (sum / count as f64) as f32;
+
+            gain = ramp.min(self.release * gain + (1.0 - self.release));
+            // rounding in the average can leave a hair above the ceiling
+            samples[i] = (samples[i] * gain).clamp(-self.ceiling, self.ceiling);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// Reports what this build supports, for --info.
+
+use crate::automation::Target;
+use crate::effects::{Biquad, Vowel, BUTTERWORTH_Q};
+use crate::modulation::{Dest, Shape};
+use crate::options::{Category, Options, FLAGS};
+use crate::resample::Interpolation;
+use crate::wav::BitDepth;
+use crate::{frequency_to_note_name, note_gap, schedule, Note, Nyquist, Resolved, Wavetable};
+use crate::{DURATION_SYNTAX, NOTE_SYNTAX, SAMPLE_RATE, WAVEFORMS};
+use serde_json::json;
+
+pub fn print(as_json: bool) {
+    let targets: Vec<&str> = Target::ALL.iter().map(|target| target.name()).collect();
+    let interpolations: Vec<&str> = Interpolation::ALL.iter().map(|kind| kind.name()).collect();
+    let bit_depths: Vec<&str> = BitDepth::ALL.iter().map(|depth| depth.name()).collect();
+    let vowels: Vec<&str> = Vowel::ALL.iter().map(|vowel| vowel.name()).collect();
+    let shapes: Vec<&str> = Shape::ALL.iter().map(|shape| shape.name()).collect();
+    let dests: Vec<&str> = Dest::ALL.iter().map(|dest| dest.name()).collect();
+    let nyquist: Vec<&str> = Nyquist::ALL.iter().map(|nyquist| nyquist.name()).collect();
+
+    if as_json {
+        let flags: Vec<_> = FLAGS
+            .iter()
+            .map(|flag| {
+                json!({
+                    "name": flag.name,
+                    "value": flag.value,
+                    "help": flag.help,
+                    "category": flag.category.name(),
+                })
+            })
+            .collect();
+        let info = json!({
+            "sample_rate": SAMPLE_RATE,
+            "waveforms": WAVEFORMS,
+            "note_syntax": NOTE_SYNTAX,
+            "duration_syntax": DURATION_SYNTAX,
+            "automation_targets": targets,
+            "interpolations": interpolations,
+            "bit_depths": bit_depths,
+            "formant_vowels": vowels,
+            "lfo_shapes": shapes,
+            "modulation_destinations": dests,
+            "nyquist_handling": nyquist,
+            "flags": flags,
+        });
+        println!("{}", serde_json::to_string_pretty(&info).unwrap());
+        return;
+    }
+
+    println!("Sample rate: {} Hz", SAMPLE_RATE);
+    println!("Waveforms: {}", WAVEFORMS.join(", "));
+    println!("Automation targets: {}", targets.join(", "));
+    println!("Resampling: {}", interpolations.join(", "));
+    println!("WAV bit depths: {}", bit_depths.join(", "));
+    println!("Formant vowels: {}", vowels.join(", "));
+    println!("LFO shapes: {}", shapes.join(", "));
+    println!("Modulation destinations: {}", dests.join(", "));
+    println!("Nyquist handling: {}", nyquist.join(", "));
+
+    println!("\nNotes:");
+    for rule in NOTE_SYNTAX {
+        println!("  {}", rule);
+    }
+    println!("\nDurations:");
+    for rule in DURATION_SYNTAX {
+        println!("  {}", rule);
+    }
+
+    for category in [Category::Playback, Category::Synthesis, Category::Effect, Category::Output] {
+        println!("\n{} options:", category.name());
+        for flag in FLAGS.iter().filter(|flag| flag.category == category) {
+            println!("  {}", format!("{} {}", flag.name, flag.value).trim_end());
+        }
+    }
+}
+
+// Frequencies at third-octave steps from 20 Hz up
+const RESPONSE_POINTS: usize = 31;
+
+pub fn filter_response(filters: &[(String, Biquad)]) {
+    print!("{:>9}", "Hz");
+    for (name, _) in filters {
+        print!(" {:>12}", name);
+    }
+    println!();
+
+    for i in 0..RESPONSE_POINTS {
+        let freq = 20.0 * 2.0_f32.powf(i as f32 / 3.0);
+        print!("{:>9.1}", freq);
+        for (_, filter) in filters {
+            print!(" {:>9.1} dB", filter.response_db(freq));
+        }
+        println!();
+    }
+}
+
+// Every note's resolved parameters, for --info-per-note. Stack parts and modulation follow their
+// note on indented lines, and drones come last, held from the start to the end of the song.
+pub fn notes(name: &str, notes: &[Note], drone: &[Note], options: &Options) {
+    let mut effects = Vec::new();
+    if options.autowah {
+        effects.push("autowah");
+    }
+    if options.flanger {
+        effects.push("flanger");
+    }
+    if options.threshold.is_some() {
+        effects.push("compressor");
+    }
+    if options.limit.is_some() {
+        effects.push("limiter");
+    }
+    println!("{}", name);
+    println!("  mix effects: {}", if effects.is_empty() { "none".to_string() } else { effects.join(", ") });
+    println!(
+        "  note ramps: attack {} ms, release {} ms",
+        options.note_attack, options.note_release
+    );
+
+    println!(
+        "  {:>4} {:>8} {:>7} {:>5} {:>9} {:>6} {:>5} {:>9} {:>5} {:>6} {:>11} {:>7}",
+        "#", "start", "length", "note", "Hz", "cents", "phase", "wave", "level", "accent", "cutoff", "formant"
+    );
+    let (spans, length) = schedule(notes, note_gap(options));
+    for (i, (note, span)) in notes.iter().zip(spans).enumerate() {
+        row(&i.to_string(), note, span.start, span.length, options);
+    }
+    for (i, note) in drone.iter().enumerate() {
+        row(&format!("d{}", i), note, 0, length, options);
+    }
+}
+
+fn row(label: &str, note: &Note, start: usize, length: usize, options: &Options) {
+    let Resolved { wavetable, level, brightness, cutoff } = note.resolve(options);
+    let lowpass = |cutoff: Option<f32>| {
+        cutoff.map_or("-".to_string(), |cutoff| format!("{:.0} Q{:.2}", cutoff * brightness, BUTTERWORTH_Q))
+    };
+    let wave = |wavetable: Option<&Wavetable>| if wavetable.is_some() { "wavetable" } else { "sine" };
+    let frequency = note.frequency();
+    println!(
+        "  {:>4} {:>8.3} {:>7.3} {:>5} {:>9.2} {:>6.1} {:>5} {:>9} {:>5.2} {:>6} {:>11} {:>7}",
+        label,
+        start as f32 / SAMPLE_RATE,
+        length as f32 / SAMPLE_RATE,
+        if note.is_rest() { "R".to_string() } else { frequency_to_note_name(frequency) },
+        frequency,
+        note.tune_cents,
+        note.phase.map_or("-".to_string(), |phase| format!("{:.2}", phase)),
+        wave(wavetable),
+        level,
+        if note.accent { "yes" } else { "-" },
+        lowpass(cutoff),
+        note.formant.map_or("-", |vowel| vowel.name()),
+    );
+
+    for (i, part) in note.stack.iter().enumerate() {
+        println!(
+            "         stack {}: {}, {:+} cents, gain {:.2}, cutoff {}",
+            i,
+            wave(part.wavetable.as_ref()),
+            part.tune_cents,
+            part.gain.unwrap_or(1.0),
+            lowpass(part.cutoff)
+        );
+    }
+    let clock = if note.song_modulation { "song" } else { "note" };
+    for (i, lfo) in note.lfos.iter().enumerate() {
+        println!(
+            "         lfo{}: {} {} Hz, depth {:.2}, on {} time",
+            i + 1,
+            lfo.shape.name(),
+            lfo.rate,
+            lfo.depth.unwrap_or(1.0),
+            clock
+        );
+    }
+    for route in &note.routes {
+        println!("         {} -> {} {}", route.source, route.dest.name(), route.amount);
+    }
+}
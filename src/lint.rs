@@ -0,0 +1,65 @@
+// Checks song files for likely authoring mistakes, for --lint. Works on the parsed notes only,
+// nothing is rendered.
+
+use crate::Note;
+
+// Outside this a note is probably a typo rather than intended
+const LOWEST_FREQ: f32 = 20.0;
+const HIGHEST_FREQ: f32 = 20000.0;
+
+// Prints a line for each problem in the song and returns how many there were
+pub fn check(name: &str, notes: &[Note]) -> usize {
+    let mut problems = Vec::new();
+    let mut repeats = Vec::new();
+    if notes.is_empty() {
+        problems.push("the song has no notes, add some or remove the file".to_string());
+    }
+
+    let frequencies: Vec<Result<f32, String>> = notes.iter().map(Note::try_frequency).collect();
+
+    for (i, note) in notes.iter().enumerate() {
+        let frequency = match &frequencies[i] {
+            Ok(frequency) => *frequency,
+            Err(err) => {
+                problems.push(format!("note {}: {}", i, err));
+                continue;
+            }
+        };
+        if note.duration <= 0.0 {
+            problems.push(format!("note {} has a duration of {}, give it a positive length", i, note.duration));
+        }
+        if !note.is_rest() && !(LOWEST_FREQ..=HIGHEST_FREQ).contains(&frequency) {
+            problems.push(format!(
+                "note {} '{}' is {:.1} Hz, outside the audible {}-{} Hz, check the octave",
+                i, note.note, frequency, LOWEST_FREQ, HIGHEST_FREQ
+            ));
+        }
+
+        let Some(previous) = i.checked_sub(1).map(|j| &notes[j]) else {
+            continue;
+        };
+        let same = frequencies[i - 1] == Ok(frequency)
+            && previous.duration == note.duration
+            && previous.velocity() == note.velocity()
+            && previous.start == note.start;
+        if same && note.start.is_some() {
+            problems.push(format!("note {} is an exact copy of note {} playing over it, remove one", i, i - 1));
+        } else if same && !note.is_rest() && !note.articulate {
+            repeats.push(i);
+        }
+    }
+    for problem in &problems {
+        println!("{}: {}", name, problem);
+    }
+    // repeated notes are usual in a melody, so these are summed up and aren't counted as problems
+    if let Some(first) = repeats.first() {
+        println!(
+            "{}: hint: {} notes repeat the one before exactly, starting at note {}. If they should be \
+             tied use --legato, marking any that should stay separate as articulate.",
+            name,
+            repeats.len(),
+            first
+        );
+    }
+    problems.len()
+}
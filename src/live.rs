@@ -0,0 +1,105 @@
+// Plays notes as they arrive over UDP, one text message per datagram:
+//
+//   on <note> [velocity]
+//   off <note>
+//
+// where <note> is anything a song file accepts, e.g. "C#4" or "440hz".
+
+use crate::{Note, DEFAULT_AMPLITUDE, SAMPLE_RATE};
+use rodio::{OutputStream, Source};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const ATTACK_SECONDS: f32 = 0.005;
+const RELEASE_SECONDS: f32 = 0.2;
+
+// A sine that sounds until `released` is set, then fades out over the release time.
+struct Voice {
+    frequency: f32,
+    velocity: f32,
+    phase: f32,
+    level: f32,
+    released: Arc<AtomicBool>,
+}
+
+impl Iterator for Voice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.released.load(Ordering::Relaxed) {
+            self.level -= 1.0 / (RELEASE_SECONDS * SAMPLE_RATE);
+            if self.level <= 0.0 {
+                return None;
+            }
+        } else {
+            self.level = (self.level + 1.0 / (ATTACK_SECONDS * SAMPLE_RATE)).min(1.0);
+        }
+
+        let output = (2.0 * PI * self.phase).sin();
+        self.phase = (self.phase + self.frequency / SAMPLE_RATE).fract();
+        Some(output * DEFAULT_AMPLITUDE * self.velocity * self.level)
+    }
+}
+
+impl Source for Voice {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE as u32
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+pub fn run(address: &str) -> Result<(), String> {
+    let socket = UdpSocket::bind(address).map_err(|err| err.to_string())?;
+    let (_stream, output_stream_handle) = OutputStream::try_default().map_err(|err| err.to_string())?;
+    println!("Listening on {}", address);
+
+    let mut held: HashMap<String, Arc<AtomicBool>> = HashMap::new();
+    let mut buffer = [0; 512];
+    loop {
+        let (len, _) = socket.recv_from(&mut buffer).map_err(|err| err.to_string())?;
+        let message = String::from_utf8_lossy(&buffer[..len]);
+        let mut words = message.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (Some("on"), Some(name)) => {
+                let velocity = words.next().and_then(|v| v.parse().ok()).unwrap_or(1.0);
+                let note = Note { note: name.to_string(), ..Default::default() };
+                let frequency = match note.try_frequency() {
+                    Ok(frequency) => frequency,
+                    Err(err) => {
+                        eprintln!("Ignoring message '{}': {}", message.trim(), err);
+                        continue;
+                    }
+                };
+
+                let released = Arc::new(AtomicBool::new(false));
+                if let Some(previous) = held.insert(name.to_string(), released.clone()) {
+                    previous.store(true, Ordering::Relaxed);
+                }
+                let voice = Voice { frequency, velocity, phase: 0.0, level: 0.0, released };
+                output_stream_handle.play_raw(voice).map_err(|err| err.to_string())?;
+            }
+            (Some("off"), Some(name)) => {
+                if let Some(released) = held.remove(name) {
+                    released.store(true, Ordering::Relaxed);
+                }
+            }
+            _ => eprintln!("Ignoring message '{}'", message.trim()),
+        }
+    }
+}
@@ -1,53 +1,130 @@
+mod analysis;
+mod automation;
+mod effects;
+mod info;
+mod lint;
+mod live;
+mod modulation;
+mod options;
+mod plot;
+mod png;
+mod preset;
+mod resample;
+mod rng;
+mod roll;
+mod tempo;
+mod text;
+mod wav;
+
+use automation::{Lane, CUTOFF_UPDATE_INTERVAL};
+use options::Options;
+use modulation::{Dest, Lfo, Route};
+use rng::Rng;
+use rodio::buffer::SamplesBuffer;
 use rodio::{OutputStream, Sink, Source};
-use serde::Deserialize;
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use std::f32::consts::PI;
 use std::path::Path;
 
 const SAMPLE_RATE: f32 = 44100.0;
 const A4_FREQ: f32 = 440.0;
 const OCTAVE_SEMITONES: i32 = 12;
+// Silence after every note so repeated pitches are heard separately
+const NOTE_GAP: f32 = 0.005;
+// Half of full scale leaves headroom so notes don't clip
+const DEFAULT_AMPLITUDE: f32 = 0.5;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Default)]
 struct Note {
+    #[serde(default)]
     note: String,
-    duration: f32
+    #[serde(default)]
+    duration: f32,
+    // Absolute placement in seconds from the start of the song, instead of following the
+    // previous note. `end` sets the duration.
+    start: Option<f32>,
+    end: Option<f32>,
+    // Literal frequency in Hz, takes precedence over `note`
+    freq: Option<f32>,
+    // Static fine tuning, 100 cents to a semitone
+    #[serde(default)]
+    tune_cents: f32,
+    // Starting phase as a fraction of a cycle, 0.25 starts a sine at its peak
+    phase: Option<f32>,
+    // Play the note this many times in a row
+    repeat: Option<u32>,
+    // Single-cycle waveform to play instead of a sine
+    wavetable: Option<Wavetable>,
+    // Loudness from 0 to 1
+    velocity: Option<f32>,
+    // Timbres picked by velocity, the first layer whose range contains it wins
+    #[serde(default)]
+    layers: Vec<Layer>,
+    // Vowel shaping with formant filters: ah, eh, ee, oh or oo
+    formant: Option<effects::Vowel>,
+    // Modulation sources, and where they go, see the modulation module
+    #[serde(default)]
+    lfos: Vec<Lfo>,
+    #[serde(default, rename = "mod")]
+    routes: Vec<Route>,
+    // Set when the modulation is the song's, whose LFOs run on song time instead of restarting
+    // with each note
+    #[serde(skip)]
+    song_modulation: bool,
+    // Extra voices sounding together with the note, e.g. a sine an octave down under a wavetable
+    #[serde(default)]
+    stack: Vec<Part>,
+    // Chance from 0 to 1 that the note sounds, otherwise it is a rest of the same length
+    probability: Option<f32>,
+    // Keeps --legato from tying this note to the previous one
+    #[serde(default)]
+    articulate: bool,
+    // Louder and, through a layer's cutoff, brighter by the --accent-* amounts
+    #[serde(default)]
+    accent: bool,
 }
 
 impl Note {
-    // f = 2^(n/12 * 440) where n is the number of semitones above or below A4.
-    fn frequency(self: &Self) -> f32 {
-        let note: char;
+    fn is_rest(&self) -> bool {
+        self.freq.is_none() && self.note.eq_ignore_ascii_case("r")
+    }
+
+    fn velocity(&self) -> f32 {
+        self.velocity.unwrap_or(1.0)
+    }
 
+    fn layer(&self) -> Option<&Layer> {
+        let velocity = self.velocity();
+        self.layers.iter().find(|layer| layer.velocity[0] <= velocity && velocity <= layer.velocity[1])
+    }
 
-        let relative_octave: i32;
-        let mut accidental_offset:i32 = 0;
+    // f = 2^(n/12 * 440) where n is the number of semitones above or below A4.
+    fn try_frequency(&self) -> Result<f32, String> {
+        if self.is_rest() {
+            return Ok(0.0);
+        }
+        let tune = self.tune_cents / 100.0;
+        let detune = |freq: f32| freq * 2.0_f32.powf(tune / 12.0);
 
-        match self.note.len() {
-            2 => {
-                note = self.note.chars().nth(0).unwrap();
-                relative_octave = self.note.chars().nth(1).unwrap().to_digit(10).unwrap() as i32 - 4;
-            }
-            3 => {
-                note = self.note.chars().nth(0).unwrap();
-                let accidental = self.note.chars().nth(1).unwrap();
-                accidental_offset = match accidental {
-                    'b' => -1,
-                    '#' => 1,
-                    _ => {
-                        panic!("Invalid accidental: {}", accidental);
-                    }
-                };
-                
-                relative_octave = self.note.chars().nth(2).unwrap().to_digit(10).unwrap() as i32 - 4;
-            }
-            _ => {
-                panic!("Invalid note: {}", &self.note);
+        // a rest is R, not 0 Hz
+        let positive = |hz: f32| hz.is_finite() && hz > 0.0;
+        if let Some(freq) = self.freq {
+            if !positive(freq) {
+                return Err(format!("Invalid freq {}, expected a positive number of Hz", freq));
             }
+            return Ok(detune(freq));
+        }
+        if let Some(hz) = self.note.to_lowercase().strip_suffix("hz") {
+            let hz = hz.trim().parse().ok().filter(|&hz| positive(hz));
+            return Ok(detune(hz.ok_or(format!("Invalid frequency '{}'", self.note))?));
         }
 
+        // letter, any number of accidentals, then the octave as a signed number
+        let mut chars = self.note.chars().peekable();
         // Semitones that A4/B4/C4/etc is from A4
-        let n: i32 = match note {
+        let n: i32 = match chars.next().ok_or("Missing note name")?.to_ascii_uppercase() {
             'A' => 0,
             'B' => 2,
             'C' => -9,
@@ -55,23 +132,349 @@ impl Note {
             'E' => -5,
             'F' => -4,
             'G' => -2,
-            _ => {
-                panic!("Invalid note: {}", note);
+            _ => return Err(format!("Invalid note '{}'", self.note)),
+        };
+        let mut accidental_offset: i32 = 0;
+        while let Some(accidental) = chars.next_if(|c| !c.is_ascii_digit() && *c != '-') {
+            accidental_offset += match accidental {
+                'b' => -1,
+                '#' => 1,
+                'x' => 2,
+                _ => return Err(format!("Invalid accidental '{}' in '{}'", accidental, self.note)),
+            };
+        }
+        let octave: String = chars.collect();
+        let octave: i32 = octave.parse().map_err(|_| format!("Invalid octave in '{}'", self.note))?;
+        let out_of_range = || format!("Octave of '{}' is out of range", self.note);
+
+        let semitones_from_a4 = (octave - 4)
+            .checked_mul(OCTAVE_SEMITONES)
+            .and_then(|semitones| semitones.checked_add(n + accidental_offset))
+            .ok_or_else(out_of_range)? as f32
+            + tune;
+
+        let frequency = 2.0_f32.powf(semitones_from_a4 / 12.0) * A4_FREQ;
+        if !positive(frequency) {
+            return Err(out_of_range());
+        }
+        Ok(frequency)
+    }
+
+    // For notes from load_song, which has already checked every name
+    fn frequency(&self) -> f32 {
+        self.try_frequency().expect("note names are checked when the song is loaded")
+    }
+
+    fn resolve(&self, options: &Options) -> Resolved<'_> {
+        let layer = self.layer();
+        let (level, brightness) = if self.accent {
+            (self.velocity() * effects::from_db(options.accent_gain), options.accent_brightness)
+        } else {
+            (self.velocity(), 1.0)
+        };
+        Resolved {
+            wavetable: layer.and_then(|layer| layer.wavetable.as_ref()).or(self.wavetable.as_ref()),
+            level,
+            brightness,
+            cutoff: layer.and_then(|layer| layer.cutoff),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+struct Layer {
+    // Inclusive [low, high] velocity range
+    velocity: [f32; 2],
+    wavetable: Option<Wavetable>,
+    // Lowpass cutoff in Hz
+    cutoff: Option<f32>,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+struct Part {
+    wavetable: Option<Wavetable>,
+    // Lowpass cutoff in Hz
+    cutoff: Option<f32>,
+    // Offset from the note's pitch, -1200 for an octave below
+    #[serde(default)]
+    tune_cents: f32,
+    // Level relative to the note
+    gain: Option<f32>,
+}
+
+// Either the samples of one cycle, or a .wav/.json file holding them relative to the songs directory
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+enum Wavetable {
+    Samples(Vec<f32>),
+    File(String),
+}
+
+impl Wavetable {
+    fn load(path: &str) -> Result<Vec<f32>, String> {
+        let full_path = format!("songs/{}", path);
+        let samples: Vec<f32> = if path.ends_with(".wav") {
+            let mut reader = hound::WavReader::open(&full_path).map_err(|err| err.to_string())?;
+            let spec = reader.spec();
+            let channels = spec.channels as usize;
+            let samples: Vec<f32> = match spec.sample_format {
+                hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+                hound::SampleFormat::Int => {
+                    let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                    reader.samples::<i32>().map(|s| s.map(|s| s as f32 / scale)).collect()
+                }
             }
+            .map_err(|err| err.to_string())?;
+            // only the first channel is used
+            samples.into_iter().step_by(channels).collect()
+        } else {
+            let content = std::fs::read_to_string(&full_path).map_err(|err| err.to_string())?;
+            serde_json::from_str(&content).map_err(|err| err.to_string())?
+        };
+
+        if samples.is_empty() {
+            return Err("wavetable is empty".to_string());
+        }
+        Ok(samples)
+    }
+
+    // Replaces a file reference with its samples, loading each file only once. Inline samples
+    // are checked the same way as a file's.
+    fn resolve(
+        wavetable: &mut Option<Wavetable>,
+        loaded: &mut HashMap<String, Vec<f32>>,
+    ) -> Result<(), String> {
+        match wavetable {
+            Some(Wavetable::File(path)) => {
+                if !loaded.contains_key(path) {
+                    let table = Wavetable::load(path)
+                        .map_err(|err| format!("Failed to load wavetable '{}': {}", path, err))?;
+                    loaded.insert(path.clone(), table);
+                }
+                *wavetable = Some(Wavetable::Samples(loaded[path.as_str()].clone()));
+            }
+            Some(Wavetable::Samples(table)) if table.is_empty() => return Err("wavetable is empty".to_string()),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn samples(&self) -> Option<Vec<f32>> {
+        match self {
+            Wavetable::Samples(table) => Some(table.clone()),
+            Wavetable::File(_) => None,
+        }
+    }
+}
+
+// Shown by --info, keep in step with Note::frequency and Event
+const NOTE_SYNTAX: &[&str] = &[
+    "letter A-G in either case, then any accidentals: b (flat), bb, # (sharp) or x (double sharp), then an octave from -1 up, e.g. C4, f#3, Bb5, Cx4, C-1, G10",
+    "R for a rest",
+    "a literal frequency such as 440hz, or a freq field in Hz which takes precedence",
+    "tune_cents: static fine tuning of the pitch, 100 cents to a semitone",
+    "accent: true to play the note louder and brighter",
+    "formant: one of the formant vowels to give the note a vowel sound",
+    "lfos and mod: LFOs of the shapes listed routed to the modulation destinations, per note or for the whole song",
+    "stack: extra voices played with the note, each with a wavetable, cutoff, tune_cents and gain",
+];
+const DURATION_SYNTAX: &[&str] = &[
+    "duration: length of the note in seconds",
+    "start/end: absolute times in seconds instead of a duration, such notes may overlap",
+    "repeat: play the note this many times in a row",
+    "{\"tuplet\": {\"span\": n, \"notes\": [...]}}: fit the notes into the time of n of them",
+    "{\"crescendo\": {\"from\": v, \"to\": v, \"notes\": [...]}}: ramp velocity across the notes, also decrescendo",
+    "{\"notes\": [...], \"drone\": [...]}: notes held for the whole song under the others",
+    "{\"notes\": [...], \"loops\": [{\"from\": i, \"to\": j, \"count\": n}]}: play notes i to j n times",
+    "a .txt song is note:beats tokens, e.g. bpm=120 time=4/4 C4:1 E4:1 G4:2 | R:4, with | barlines",
+];
+const WAVEFORMS: &[&str] = &["sine", "wavetable"];
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+// An f32 can't hold a frequency exactly halfway between semitones, so anything within this many
+// semitones of halfway counts as halfway
+const HALFWAY_TOLERANCE: f32 = 1e-4;
+
+// Inverse of Note::frequency, returns the nearest note name using sharps. A frequency halfway
+// between two semitones rounds up to the higher note.
+fn frequency_to_note_name(hz: f32) -> String {
+    let semitones_from_a4 =
+        (OCTAVE_SEMITONES as f32 * (hz / A4_FREQ).log2() + 0.5 + HALFWAY_TOLERANCE).floor() as i32;
+    // Semitones from C0, A4 being 4 octaves and 9 semitones above it
+    let from_c0 = semitones_from_a4 + 4 * OCTAVE_SEMITONES + 9;
+
+    format!(
+        "{}{}",
+        NOTE_NAMES[from_c0.rem_euclid(OCTAVE_SEMITONES) as usize],
+        from_c0.div_euclid(OCTAVE_SEMITONES)
+    )
+}
+
+// An entry in a song file, expanded into plain notes before rendering
+enum Event {
+    Tuplet { tuplet: Tuplet },
+    Crescendo { crescendo: Dynamic },
+    Decrescendo { decrescendo: Dynamic },
+    Note(Note),
+}
+
+// Picks the kind of event from its key rather than trying each in turn as an untagged enum
+// would, so a mistake in a note keeps serde's own message instead of "did not match any variant"
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = serde_json::Value::deserialize(deserializer)?;
+        let event = if let Some(tuplet) = value.get_mut("tuplet") {
+            serde_json::from_value(tuplet.take()).map(|tuplet| Event::Tuplet { tuplet })
+        } else if let Some(dynamic) = value.get_mut("crescendo") {
+            serde_json::from_value(dynamic.take()).map(|crescendo| Event::Crescendo { crescendo })
+        } else if let Some(dynamic) = value.get_mut("decrescendo") {
+            serde_json::from_value(dynamic.take()).map(|decrescendo| Event::Decrescendo { decrescendo })
+        } else {
+            serde_json::from_value(value).map(Event::Note)
         };
+        event.map_err(serde::de::Error::custom)
+    }
+}
+
+impl Event {
+    // How many members of an enclosing tuplet this event accounts for
+    fn members(&self) -> u32 {
+        match self {
+            Event::Note(note) => note.repeat.unwrap_or(1),
+            Event::Tuplet { .. } => 1,
+            Event::Crescendo { crescendo: dynamic } | Event::Decrescendo { decrescendo: dynamic } => {
+                dynamic.notes.iter().map(Event::members).sum()
+            }
+        }
+    }
+}
+
+// Fits its members into the time of `span` of them, e.g. a triplet is 3 notes with a span of 2.
+// A nested tuplet counts as a single member of its parent.
+#[derive(Deserialize)]
+struct Tuplet {
+    span: u32,
+    notes: Vec<Event>,
+}
+
+// Ramps velocity linearly from `from` on the first note to `to` on the last. Notes that set
+// their own velocity keep it.
+#[derive(Deserialize)]
+struct Dynamic {
+    from: f32,
+    to: f32,
+    notes: Vec<Event>,
+}
 
-        let semitones_from_a4 = n + relative_octave * OCTAVE_SEMITONES + accidental_offset;
+// A song file is either a bare array of notes or an object with song-level settings. The settings
+// are spelled out rather than flattened in, which would buffer the notes and lose serde's line and
+// column on a mistake in one of them.
+#[derive(Deserialize, Default)]
+struct SongFile {
+    notes: Vec<Event>,
+    #[serde(default)]
+    automation: Vec<Lane>,
+    #[serde(default)]
+    round_robin: Vec<Variation>,
+    #[serde(default)]
+    drone: Vec<Note>,
+    #[serde(default)]
+    loops: Vec<Loop>,
+    // Modulation for every note that doesn't have its own
+    #[serde(default)]
+    lfos: Vec<Lfo>,
+    #[serde(default, rename = "mod")]
+    routes: Vec<Route>,
+}
 
-        let freq = 2.0_f32.powf(semitones_from_a4 as f32 / 12.0) * A4_FREQ;
-        return freq;
+impl SongFile {
+    // Picks the form from the first character rather than trying both
+    fn parse(content: &str) -> serde_json::Result<SongFile> {
+        if content.trim_start().starts_with('[') {
+            Ok(SongFile { notes: serde_json::from_str(content)?, ..Default::default() })
+        } else {
+            serde_json::from_str(content)
+        }
     }
 }
 
+// Notes `from..=to` (indices after expansion) played `count` times in a row
+#[derive(Deserialize, Clone, Copy)]
+struct Loop {
+    from: usize,
+    to: usize,
+    count: u32,
+}
+
+// One of the alternatives cycled through on each repeat of a pitch, so fast repeats don't
+// sound identical
+#[derive(Deserialize)]
+struct Variation {
+    #[serde(default)]
+    tune_cents: f32,
+    // Used when the note doesn't set its own phase
+    phase: Option<f32>,
+    // Multiplies the note's velocity
+    gain: Option<f32>,
+}
+
+struct Song {
+    notes: Vec<Note>,
+    automation: Vec<Lane>,
+    round_robin: Vec<Variation>,
+    // Held under the whole song, their durations are ignored
+    drone: Vec<Note>,
+    loops: Vec<Loop>,
+}
+
+fn expand(events: &[Event], scale: f32, notes: &mut Vec<Note>) {
+    for event in events {
+        match event {
+            Event::Note(note) => {
+                for _ in 0..note.repeat.unwrap_or(1) {
+                    let mut note = note.clone();
+                    note.duration *= scale;
+                    notes.push(note);
+                }
+            }
+            Event::Tuplet { tuplet } => {
+                let members: u32 = tuplet.notes.iter().map(Event::members).sum();
+                if members > 0 {
+                    expand(&tuplet.notes, scale * tuplet.span as f32 / members as f32, notes);
+                }
+            }
+            Event::Crescendo { crescendo: dynamic } | Event::Decrescendo { decrescendo: dynamic } => {
+                let first = notes.len();
+                expand(&dynamic.notes, scale, notes);
+
+                let count = notes.len() - first;
+                for (i, note) in notes[first..].iter_mut().enumerate() {
+                    let position = if count > 1 { i as f32 / (count - 1) as f32 } else { 0.0 };
+                    note.velocity.get_or_insert(dynamic.from + (dynamic.to - dynamic.from) * position);
+                }
+            }
+        }
+    }
+}
+
+// Below this a note is felt more than heard, and any jump at its start lands as a thump
+const SUB_BASS_FREQ: f32 = 30.0;
+const SUB_BASS_FADE_SECONDS: f32 = 0.01;
+
 struct SineWave {
     frequency: f32,
     duration: f32,
-    current_sample: f32,
-    total_samples: f32
+    phase: f64, // position within the current cycle, from 0 to 1
+    high_precision: bool,
+    wavetable: Option<Vec<f32>>,
+    amplitude: f32, // peak output level
+    fade_in: usize, // samples to ramp up from silence over
+    attack: usize,
+    release: usize,
+    pitch: Option<Vec<f32>>, // frequency multiplier for each sample
+    current_sample: usize,
+    total_samples: usize,
 }
 
 impl SineWave {
@@ -79,10 +482,105 @@ impl SineWave {
         Self {
             frequency,
             duration,
-            current_sample: 0.0,
-            total_samples: duration * SAMPLE_RATE,
+            phase: 0.0,
+            high_precision: false,
+            wavetable: None,
+            amplitude: DEFAULT_AMPLITUDE,
+            fade_in: if frequency > 0.0 && frequency < SUB_BASS_FREQ {
+                (SUB_BASS_FADE_SECONDS * SAMPLE_RATE) as usize
+            } else {
+                0
+            },
+            attack: 0,
+            release: 0,
+            pitch: None,
+            current_sample: 0,
+            total_samples: (duration * SAMPLE_RATE).round() as usize,
         }
     }
+
+    // Overrides the length derived from the duration
+    fn with_samples(mut self, samples: usize) -> Self {
+        self.total_samples = samples;
+        self
+    }
+
+    // Sub-bass notes ignore this and always start on a zero crossing
+    fn with_phase(mut self, phase: f64) -> Self {
+        if self.fade_in == 0 {
+            self.phase = phase;
+        }
+        self
+    }
+
+    // Accumulate phase in f64 rather than f32
+    fn with_high_precision(mut self, high_precision: bool) -> Self {
+        self.high_precision = high_precision;
+        self
+    }
+
+    fn with_amplitude(mut self, amplitude: f32) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+
+    // Linear ramps in samples at the start and end of the note
+    fn with_ramps(mut self, attack: usize, release: usize) -> Self {
+        self.attack = attack;
+        self.release = release;
+        self
+    }
+
+    fn with_pitch(mut self, pitch: Option<Vec<f32>>) -> Self {
+        self.pitch = pitch;
+        self
+    }
+
+    // Play one cycle of `table` per period instead of a sine
+    fn with_wavetable(mut self, table: Option<Vec<f32>>) -> Self {
+        self.wavetable = table;
+        self
+    }
+}
+
+// Running song position. Durations are summed in f64 seconds and only converted to a sample
+// index at each boundary, so per-note rounding never accumulates and a note starts on the
+// sample nearest its exact time however far into the song it is. Every duration is an f32, and
+// f64 holds sums of those exactly over any realistic song, so rationals would gain nothing.
+#[derive(Default)]
+struct Timeline {
+    seconds: f64,
+}
+
+impl Timeline {
+    fn sample(&self) -> usize {
+        (self.seconds * SAMPLE_RATE as f64).round() as usize
+    }
+
+    fn seek(&mut self, seconds: f32) {
+        self.seconds = seconds as f64;
+    }
+
+    // Moves forward by `duration` and returns how many samples that span covers. A negative
+    // duration plays nothing, like a zero one, rather than moving back.
+    fn advance(&mut self, duration: f32) -> usize {
+        let start = self.sample();
+        self.seconds += duration.max(0.0) as f64;
+        self.sample() - start
+    }
+}
+
+// DSP state that outlives a single note when a song is rendered as one continuous voice
+#[derive(Default)]
+struct VoiceState {
+    phase: f64,
+    // The note's own lowpass and those of its stack parts by index, carried through notes
+    // without one so a rest doesn't restart them
+    filter: Option<effects::Biquad>,
+    part_filters: Vec<Option<effects::Biquad>>,
+    formant: Option<effects::Formant>,
+    // Samples the note's own LFOs have run for
+    modulation_position: usize,
 }
 
 impl Iterator for SineWave {
@@ -94,11 +592,40 @@ impl Iterator for SineWave {
             return None;
         }
 
-        let t = self.current_sample / self.sample_rate() as f32; // time in seconds
-        let output = (2.0 * PI * self.frequency * t).sin();
+        let output = match &self.wavetable {
+            Some(table) => {
+                // linear interpolation between the two nearest entries, wrapping around the cycle
+                let position = self.phase * table.len() as f64;
+                let i = position as usize % table.len();
+                let fraction = position.fract() as f32;
+                table[i] + (table[(i + 1) % table.len()] - table[i]) * fraction
+            }
+            None if self.high_precision => (2.0 * std::f64::consts::PI * self.phase).sin() as f32,
+            None => (2.0 * PI * self.phase as f32).sin(),
+        };
+
+        let frequency = match &self.pitch {
+            Some(pitch) => self.frequency * pitch[self.current_sample],
+            None => self.frequency,
+        };
+        self.phase = if self.high_precision {
+            (self.phase + frequency as f64 / self.sample_rate() as f64).fract()
+        } else {
+            (self.phase as f32 + frequency / self.sample_rate() as f32).fract() as f64
+        };
 
-        self.current_sample += 1.0;
-        Some(output * 0.5) // reduce amplitude by half to reduce clipping
+        let ramp = |position: usize, length: usize| {
+            if position < length {
+                position as f32 / length as f32
+            } else {
+                1.0
+            }
+        };
+        let remaining = self.total_samples - 1 - self.current_sample;
+        let fade = ramp(self.current_sample, self.fade_in.max(self.attack)).min(ramp(remaining, self.release));
+
+        self.current_sample += 1;
+        Some(output * self.amplitude * fade)
     }
 }
 
@@ -120,45 +647,983 @@ impl Source for SineWave {
     }
 }
 
+// Where each note sits in a song, in samples
+struct Span {
+    start: usize,
+    length: usize,
+}
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        println!("Available songs:");
-        for entry in std::fs::read_dir("songs").expect("Failed to read songs directory") {
-            if let Ok(entry) = entry {
-                println!("  {}", entry.file_name().to_string_lossy());
+// Lays the notes out one after another with `gap` seconds after each, except that a note with a
+// `start` is placed there and the ones after it follow on from it. Returns their spans and the
+// total length of the song.
+fn schedule(notes: &[Note], gap: f32) -> (Vec<Span>, usize) {
+    let mut timeline = Timeline::default();
+    let spans: Vec<Span> = notes
+        .iter()
+        .map(|note| {
+            if let Some(start) = note.start {
+                timeline.seek(start);
             }
+            let start = timeline.sample();
+            let length = timeline.advance(note.duration);
+            timeline.advance(gap);
+            Span { start, length }
+        })
+        .collect();
+    let length = spans.iter().map(|span| span.start + span.length).fold(timeline.sample(), usize::max);
+    (spans, length)
+}
+
+// A note's place in the output, for --onsets
+#[derive(Serialize)]
+struct Onset {
+    start: f32,
+    duration: f32,
+    note: String,
+    frequency: f32,
+}
+
+// What a note renders with once its velocity layer, accent and the options are applied
+struct Resolved<'a> {
+    wavetable: Option<&'a Wavetable>,
+    level: f32,
+    // Multiplier for every lowpass cutoff on the note
+    brightness: f32,
+    // Of the note itself, before brightness
+    cutoff: Option<f32>,
+}
+
+// Sum of the notes, timed notes can overlap. Kept in f64 with --high-precision.
+enum Mix {
+    Single(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl Mix {
+    fn new(length: usize, high_precision: bool) -> Self {
+        if high_precision {
+            Mix::Double(vec![0.0; length])
+        } else {
+            Mix::Single(vec![0.0; length])
         }
-        println!("\nUsage: {} <song_name>", args[0]);
-        std::process::exit(1);
     }
 
-    let mut song_path = format!("songs/{}", args[1]);
-    if !song_path.ends_with(".json") {
-        song_path.push_str(".json");
+    fn add(&mut self, start: usize, note_samples: &[f32]) {
+        match self {
+            Mix::Single(samples) => {
+                for (sample, note_sample) in samples[start..].iter_mut().zip(note_samples) {
+                    *sample += note_sample;
+                }
+            }
+            Mix::Double(samples) => {
+                for (sample, note_sample) in samples[start..].iter_mut().zip(note_samples) {
+                    *sample += *note_sample as f64;
+                }
+            }
+        }
+    }
+
+    fn into_samples(self) -> Vec<f32> {
+        match self {
+            Mix::Single(samples) => samples,
+            Mix::Double(samples) => samples.into_iter().map(|sample| sample as f32).collect(),
+        }
+    }
+}
+
+// Mixes every note into one buffer at its start sample, so overlapping notes sum. Notes starting
+// at or after `until` are skipped.
+fn render(notes: &[Note], options: &Options, until: Option<usize>) -> Vec<f32> {
+    let (spans, length) = schedule(notes, note_gap(options));
+    let mut mix = Mix::new(length, options.high_precision);
+    let mut voice = VoiceState::default();
+
+    for (note, span) in notes.iter().zip(&spans) {
+        if until.is_some_and(|until| span.start >= until) {
+            continue;
+        }
+        if !options.continuous {
+            voice = VoiceState::default();
+        }
+
+        let Resolved { wavetable, level, brightness, cutoff } = note.resolve(options);
+
+        let matrix = modulation::Matrix::new(&note.lfos, &note.routes);
+        let modulation_start = if note.song_modulation { span.start } else { voice.modulation_position };
+        let time = |i: usize| (modulation_start + i) as f32 / SAMPLE_RATE;
+        let pitch: Option<Vec<f32>> = matrix.targets(Dest::Pitch).then(|| {
+            (0..span.length)
+                .map(|i| 2.0_f32.powf(matrix.value(Dest::Pitch, time(i)) / OCTAVE_SEMITONES as f32))
+                .collect()
+        });
+
+        let phase = note.phase.map_or(voice.phase, |phase| phase.rem_euclid(1.0) as f64);
+        let ramp = |ms: f32| (ms / 1000.0 * SAMPLE_RATE) as usize;
+        let oscillator = |frequency: f32, amplitude: f32, wavetable: Option<&Wavetable>| {
+            SineWave::new(frequency, note.duration)
+                .with_samples(span.length)
+                .with_phase(phase)
+                .with_high_precision(options.high_precision)
+                .with_amplitude(DEFAULT_AMPLITUDE * amplitude)
+                .with_wavetable(wavetable.and_then(Wavetable::samples))
+                .with_ramps(ramp(options.note_attack), ramp(options.note_release))
+                .with_pitch(pitch.clone())
+        };
+        // Picks up from `state` when there is one, and leaves the filter there for the next note
+        let lowpass = |samples: &mut [f32], cutoff: Option<f32>, state: &mut Option<effects::Biquad>| {
+            let Some(cutoff) = cutoff else {
+                return;
+            };
+            let swept = |i: usize| cutoff * brightness * 2.0_f32.powf(matrix.value(Dest::Cutoff, time(i)));
+            let mut filter = match state.take() {
+                Some(mut filter) => {
+                    filter.set_lowpass(swept(0), effects::BUTTERWORTH_Q);
+                    filter
+                }
+                None => effects::Biquad::lowpass(swept(0), effects::BUTTERWORTH_Q)
+                    .with_high_precision(options.high_precision),
+            };
+            for (i, sample) in samples.iter_mut().enumerate() {
+                if matrix.targets(Dest::Cutoff) && i % CUTOFF_UPDATE_INTERVAL == 0 {
+                    filter.set_lowpass(swept(i), effects::BUTTERWORTH_Q);
+                }
+                *sample = filter.process(*sample);
+            }
+            *state = Some(filter);
+        };
+
+        let mut primary = oscillator(note.frequency(), level, wavetable);
+        let mut note_samples: Vec<f32> = (&mut primary).collect();
+        voice.phase = primary.phase;
+        lowpass(&mut note_samples, cutoff, &mut voice.filter);
+        voice.modulation_position += span.length;
+
+        if voice.part_filters.len() < note.stack.len() {
+            voice.part_filters.resize_with(note.stack.len(), || None);
+        }
+        for (part, filter) in note.stack.iter().zip(voice.part_filters.iter_mut()) {
+            let frequency = note.frequency() * 2.0_f32.powf(part.tune_cents / 1200.0);
+            let mut part_samples: Vec<f32> =
+                oscillator(frequency, level * part.gain.unwrap_or(1.0), part.wavetable.as_ref()).collect();
+            lowpass(&mut part_samples, part.cutoff, filter);
+            for (sample, part_sample) in note_samples.iter_mut().zip(part_samples) {
+                *sample += part_sample;
+            }
+        }
+
+        if let Some(vowel) = note.formant {
+            let formant = match voice.formant.take() {
+                Some(mut formant) => {
+                    formant.set_vowel(vowel);
+                    formant
+                }
+                None => effects::Formant::new(vowel).with_high_precision(options.high_precision),
+            };
+            voice.formant.insert(formant).process(&mut note_samples);
+        }
+
+        if let Some(target) = options.normalize_per_note {
+            // the target is for full velocity, quieter notes stay proportionally quieter
+            let rms = analysis::rms(&note_samples);
+            if rms > 0.0 {
+                let gain = target * level / rms;
+                note_samples.iter_mut().for_each(|sample| *sample *= gain);
+            }
+        }
+        // after normalizing, which would otherwise even out the tremolo
+        if matrix.targets(Dest::Amplitude) {
+            for (i, sample) in note_samples.iter_mut().enumerate() {
+                *sample *= (1.0 + matrix.value(Dest::Amplitude, time(i))).max(0.0);
+            }
+        }
+        mix.add(span.start, &note_samples);
+    }
+    mix.into_samples()
+}
+
+// Writes each loop region out `count` times. Timed notes in a repeat, and after the region, move
+// later by the region's length.
+fn unroll_loops(mut notes: Vec<Note>, loops: &[Loop], gap: f32) -> Result<Vec<Note>, String> {
+    let mut loops = loops.to_vec();
+    // last region first, so unrolling it leaves the indices of earlier ones alone
+    loops.sort_by_key(|region| std::cmp::Reverse(region.from));
+    if let Some(pair) = loops.windows(2).find(|pair| pair[1].to >= pair[0].from) {
+        return Err(format!("Loops at notes {} and {} overlap", pair[1].from, pair[0].from));
+    }
+
+    for region in loops {
+        if region.from > region.to || region.to >= notes.len() {
+            return Err(format!(
+                "Loop {}-{} is outside the song's notes 0 to {}",
+                region.from,
+                region.to,
+                notes.len().saturating_sub(1)
+            ));
+        }
+        if region.count == 0 {
+            return Err(format!("Loop {}-{} has a count of 0, it needs to play at least once", region.from, region.to));
+        }
+        let (spans, _) = schedule(&notes, gap);
+        let end = spans[region.to].start + spans[region.to].length + (gap * SAMPLE_RATE).round() as usize;
+        let length = (end - spans[region.from].start) as f32 / SAMPLE_RATE;
+        let shift = |note: &Note, by: f32| Note { start: note.start.map(|start| start + by), ..note.clone() };
+
+        let after: Vec<Note> = notes.drain(region.to + 1..).collect();
+        let body: Vec<Note> = notes[region.from..].to_vec();
+        for repeat in 1..region.count {
+            notes.extend(body.iter().map(|note| shift(note, length * repeat as f32)));
+        }
+        let moved = length * (region.count - 1) as f32;
+        notes.extend(after.iter().map(|note| shift(note, moved)));
+    }
+    Ok(notes)
+}
+
+// Keeps notes `from..=to` (indices into the expanded note list), for working on one passage
+fn slice_notes(notes: Vec<Note>, from: Option<usize>, to: Option<usize>) -> Result<Vec<Note>, String> {
+    let last = notes.len().saturating_sub(1);
+    let from = from.unwrap_or(0);
+    let to = to.unwrap_or(last);
+    if let Some(index) = [from, to].into_iter().find(|&index| notes.is_empty() || index > last) {
+        return Err(format!("Note index {} is out of range, the song has notes 0 to {}", index, last));
+    }
+    if from > to {
+        return Err(format!("--from-note {} is after --to-note {}", from, to));
+    }
+    Ok(notes.into_iter().skip(from).take(to - from + 1).collect())
+}
+
+// What to do with notes pitched above the Nyquist limit, where they would alias
+#[derive(Clone, Copy, PartialEq)]
+enum Nyquist {
+    Warn,
+    Clamp,
+    Skip,
+}
+
+impl Nyquist {
+    const ALL: [Nyquist; 3] = [Nyquist::Warn, Nyquist::Clamp, Nyquist::Skip];
+
+    fn name(self) -> &'static str {
+        match self {
+            Nyquist::Warn => "warn",
+            Nyquist::Clamp => "clamp",
+            Nyquist::Skip => "skip",
+        }
+    }
+}
+
+impl std::str::FromStr for Nyquist {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Nyquist::ALL
+            .into_iter()
+            .find(|nyquist| nyquist.name() == s)
+            .ok_or(format!("Unknown Nyquist handling '{}', expected warn, clamp or skip", s))
+    }
+}
+
+// Checks every voice of each note, its stack parts too, at the highest its pitch LFOs take it
+fn check_nyquist(song: &str, kind: &str, notes: &mut [Note], handling: Nyquist) {
+    let limit = SAMPLE_RATE / 2.0;
+    let action = match handling {
+        Nyquist::Warn => "it will alias",
+        Nyquist::Clamp => "clamping it",
+        Nyquist::Skip => "skipping it",
+    };
+    for (i, note) in notes.iter_mut().enumerate() {
+        if note.is_rest() {
+            continue;
+        }
+        let peak = modulation::Matrix::new(&note.lfos, &note.routes).peak(Dest::Pitch);
+        let lfo = 2.0_f32.powf(peak / OCTAVE_SEMITONES as f32);
+        let warn = |voice: String, frequency: f32| {
+            let reach = if peak > 0.0 { " with its pitch LFOs" } else { "" };
+            eprintln!(
+                "Warning: {} {} {}{} reaches {:.0} Hz{}, above the {} Hz Nyquist limit, {}",
+                song, kind, i, voice, frequency, reach, limit, action
+            );
+        };
+
+        let highest = note.frequency() * lfo;
+        if highest >= limit {
+            let name = if note.note.is_empty() { String::new() } else { format!(" '{}'", note.note) };
+            warn(name, highest);
+            match handling {
+                Nyquist::Warn => {}
+                // just under, a sine at exactly Nyquist only ever lands on its zero crossings
+                Nyquist::Clamp => {
+                    note.freq = Some((limit - 1.0) / lfo);
+                    note.tune_cents = 0.0;
+                }
+                Nyquist::Skip => {
+                    note.note = "R".to_string();
+                    note.freq = None;
+                    continue;
+                }
+            }
+        }
+
+        let frequency = note.frequency();
+        let mut part_index = 0;
+        note.stack.retain_mut(|part| {
+            part_index += 1;
+            let highest = frequency * 2.0_f32.powf(part.tune_cents / 1200.0) * lfo;
+            if highest < limit {
+                return true;
+            }
+            warn(format!(" stack part {}", part_index - 1), highest);
+            match handling {
+                Nyquist::Warn => true,
+                Nyquist::Clamp => {
+                    part.tune_cents = 1200.0 * ((limit - 1.0) / (frequency * lfo)).log2();
+                    true
+                }
+                Nyquist::Skip => false,
+            }
+        });
+    }
+}
+
+// Turns each note with a probability into a rest unless its draw comes in under it
+fn roll_probabilities(notes: &mut [Note], rng: &mut Rng) {
+    for note in notes.iter_mut() {
+        if let Some(probability) = note.probability {
+            if rng.next_f32("probability") >= probability {
+                note.note = "R".to_string();
+                note.freq = None;
+            }
+        }
+    }
+}
+
+// Gives the nth consecutive trigger of a pitch the nth variation, wrapping around. Rests don't
+// break a run.
+fn round_robin(notes: &mut [Note], variations: &[Variation]) {
+    if variations.is_empty() {
+        return;
+    }
+    let mut previous = None;
+    let mut count = 0;
+    for note in notes.iter_mut().filter(|note| !note.is_rest()) {
+        let frequency = note.frequency();
+        count = if previous == Some(frequency) { count + 1 } else { 0 };
+        previous = Some(frequency);
+
+        let variation = &variations[count % variations.len()];
+        note.tune_cents += variation.tune_cents;
+        note.phase = note.phase.or(variation.phase);
+        if let Some(gain) = variation.gain {
+            note.velocity = Some(note.velocity() * gain);
+        }
     }
+}
+
+// Ties runs of the same pitch into single sustained notes, with no gap and continuous phase. A
+// note that sounds different in any other way is played again rather than tied, since tying
+// would keep only the first note's sound.
+fn legato(notes: Vec<Note>) -> Vec<Note> {
+    let mut tied: Vec<Note> = Vec::with_capacity(notes.len());
+    for note in notes {
+        if let Some(previous) = tied.last_mut() {
+            if !note.articulate
+                && note.start.is_none()
+                && previous.frequency() == note.frequency()
+                && previous.velocity() == note.velocity()
+                && previous.accent == note.accent
+                && previous.phase == note.phase
+                && previous.wavetable == note.wavetable
+                && previous.layers == note.layers
+                && previous.stack == note.stack
+                && previous.formant == note.formant
+                && previous.lfos == note.lfos
+                && previous.routes == note.routes
+            {
+                previous.duration += note.duration;
+                continue;
+            }
+        }
+        tied.push(note);
+    }
+    tied
+}
+
+// Exits with a message on any note name that doesn't parse
+fn load_song(song: &str) -> Song {
+    let loaded = read_song(song);
+    let labelled = loaded.notes.iter().map(|note| ("note", note))
+        .chain(loaded.drone.iter().map(|note| ("drone", note)));
+    let mut counts = HashMap::new();
+    for (kind, note) in labelled {
+        let i = counts.entry(kind).or_insert(0);
+        if let Err(err) = note.try_frequency() {
+            println!("{} {} {}: {}", song, kind, i, err);
+            std::process::exit(1);
+        }
+        *i += 1;
+    }
+    loaded
+}
+
+// Reads a song without checking its note names, which --lint reports itself
+fn read_song(song: &str) -> Song {
+    // a name without an extension can be either a JSON or a text song
+    let song_path = if song.ends_with(".json") || song.ends_with(".txt") {
+        format!("songs/{}", song)
+    } else {
+        let json = format!("songs/{}.json", song);
+        let text = format!("songs/{}.txt", song);
+        if !Path::new(&json).exists() && Path::new(&text).exists() { text } else { json }
+    };
 
     if !Path::new(&song_path).exists() {
-        println!("Song '{}' not found", args[1]);
+        println!("Song '{}' not found", song);
         std::process::exit(1);
     }
 
-    println!("Playing: {}", args[1]);
     let file_content = std::fs::read_to_string(&song_path)
         .expect("Failed to read song file");
 
-    let notes: Vec<Note> = serde_json::from_str(&file_content)
-        .expect("Failed to parse JSON");
+    let file = if song_path.ends_with(".txt") {
+        text::parse(&file_content)
+            .map(|notes| SongFile { notes: notes.into_iter().map(Event::Note).collect(), ..Default::default() })
+    } else {
+        SongFile::parse(&file_content).map_err(|err| err.to_string())
+    };
+    let SongFile { notes: events, automation, round_robin, mut drone, loops, lfos, routes } =
+        file.unwrap_or_else(|err| {
+            println!("{}: {}", song, err);
+            std::process::exit(1);
+        });
+    let mut notes = Vec::new();
+    expand(&events, 1.0, &mut notes);
+
+    let labelled = notes.iter_mut().map(|note| ("note", note)).chain(drone.iter_mut().map(|note| ("drone", note)));
+    let mut counts = HashMap::new();
+    for (kind, note) in labelled {
+        let i = counts.entry(kind).or_insert(0);
+        if note.lfos.is_empty() && note.routes.is_empty() {
+            note.lfos = lfos.clone();
+            note.routes = routes.clone();
+            note.song_modulation = true;
+        }
+        if let Err(err) = modulation::validate(&note.lfos, &note.routes) {
+            println!("{} {} {}: {}", song, kind, i, err);
+            std::process::exit(1);
+        }
+        *i += 1;
+    }
+    if notes.iter().chain(&drone).any(|note| note.routes.iter().any(|route| route.dest == Dest::Pan)) {
+        eprintln!("Warning: ignoring pan modulation, output is mono");
+    }
+
+    for (i, note) in notes.iter_mut().enumerate() {
+        match (note.start, note.end) {
+            (Some(start), Some(end)) if end > start => note.duration = end - start,
+            (Some(_), Some(_)) | (None, Some(_)) => {
+                println!("{} note {}: end needs a start before it", song, i);
+                std::process::exit(1);
+            }
+            _ => {}
+        }
+    }
+    // an export of absolute times needn't be in order
+    if notes.iter().all(|note| note.start.is_some()) {
+        notes.sort_by(|a, b| a.start.unwrap().total_cmp(&b.start.unwrap()));
+    }
+
+    let mut loaded = HashMap::new();
+    let labelled = notes.iter_mut().map(|note| ("note", note))
+        .chain(drone.iter_mut().map(|note| ("drone", note)));
+    let mut counts = HashMap::new();
+    for (kind, note) in labelled {
+        let i = counts.entry(kind).or_insert(0);
+        let tables = std::iter::once(&mut note.wavetable)
+            .chain(note.layers.iter_mut().map(|layer| &mut layer.wavetable))
+            .chain(note.stack.iter_mut().map(|part| &mut part.wavetable));
+        for wavetable in tables {
+            if let Err(err) = Wavetable::resolve(wavetable, &mut loaded) {
+                println!("{} {} {}: {}", song, kind, i, err);
+                std::process::exit(1);
+            }
+        }
+        *i += 1;
+    }
+
+    for (i, note) in notes.iter().enumerate() {
+        if let (Some(freq), false) = (note.freq, note.note.is_empty()) {
+            eprintln!(
+                "Warning: {} note {} has both '{}' and freq, using {} Hz (nearest {})",
+                song, i, note.note, freq, frequency_to_note_name(freq)
+            );
+        }
+    }
+    Song { notes, automation, round_robin, drone, loops }
+}
+
+// The ramps take over from the gap in keeping repeated notes apart
+fn note_gap(options: &Options) -> f32 {
+    if options.note_attack > 0.0 || options.note_release > 0.0 {
+        0.0
+    } else {
+        NOTE_GAP
+    }
+}
+
+fn gap_samples(options: &Options) -> usize {
+    (options.gap * SAMPLE_RATE).round() as usize
+}
+
+fn preview_samples(options: &Options) -> Option<usize> {
+    options.preview_seconds.map(|seconds| (seconds * SAMPLE_RATE).round() as usize)
+}
+
+// Songs back to back with --gap between them, each with its own automation applied, then --tail
+fn render_songs(songs: &[Song], options: &Options) -> Vec<f32> {
+    let limit = preview_samples(options);
+    let mut samples = Vec::new();
+
+    for (i, song) in songs.iter().enumerate() {
+        if i > 0 {
+            samples.resize(samples.len() + gap_samples(options), 0.0);
+        }
+
+        if limit.is_some_and(|limit| samples.len() >= limit) {
+            break;
+        }
+        // skip rendering notes that would start after the preview ends, which with timed notes
+        // needn't all be at the end of the list
+        let remaining = limit.map(|limit| limit - samples.len());
+
+        let mut song_samples = render(&song.notes, options, remaining);
+        if !song.drone.is_empty() {
+            let seconds = song_samples.len() as f32 / SAMPLE_RATE;
+            let drone: Vec<Note> = song.drone.iter()
+                .map(|note| Note { start: Some(0.0), end: None, duration: seconds, ..note.clone() })
+                .collect();
+            // one sum of two f32 renders, which f64 would round to the same result
+            for (sample, drone_sample) in song_samples.iter_mut().zip(render(&drone, options, None)) {
+                *sample += drone_sample;
+            }
+        }
+        if let Some(remaining) = remaining {
+            song_samples.truncate(remaining);
+        }
+        automation::apply(&song.automation, &mut song_samples, options.high_precision);
+        samples.extend(song_samples);
+    }
+
+    if let Some(limit) = limit {
+        samples.truncate(limit);
+    }
+    // silence for the effects in post_process to ring out into
+    samples.resize(samples.len() + (options.tail * SAMPLE_RATE).round() as usize, 0.0);
+    samples
+}
+
+fn onsets(songs: &[Song], options: &Options) -> Vec<Onset> {
+    let mut onsets = Vec::new();
+    let mut offset = 0;
+    for song in songs {
+        let (spans, length) = schedule(&song.notes, note_gap(options));
+        for (note, span) in song.notes.iter().zip(spans) {
+            if note.is_rest() {
+                continue;
+            }
+            let frequency = note.frequency();
+            onsets.push(Onset {
+                start: (offset + span.start) as f32 / SAMPLE_RATE,
+                duration: span.length as f32 / SAMPLE_RATE,
+                note: frequency_to_note_name(frequency),
+                frequency,
+            });
+        }
+        offset += length + gap_samples(options);
+    }
+
+    if let Some(seconds) = options.preview_seconds {
+        onsets.retain(|onset| onset.start < seconds);
+        for onset in onsets.iter_mut() {
+            onset.duration = onset.duration.min(seconds - onset.start);
+        }
+    }
+    onsets
+}
+
+// Effects on the whole mix, then sample rate conversion. Returns the output and its rate.
+fn post_process(mut samples: Vec<f32>, options: &Options) -> (Vec<f32>, u32) {
+    if options.autowah {
+        effects::AutoWah::new(options.wah_sensitivity, options.wah_min, options.wah_max)
+            .with_high_precision(options.high_precision)
+            .process(&mut samples);
+    }
+    if options.flanger {
+        effects::Flanger::new(options.flanger_rate, options.flanger_depth, options.flanger_feedback)
+            .process(&mut samples);
+    }
+    if let Some(threshold) = options.threshold {
+        effects::Compressor::new(threshold, options.ratio, options.attack, options.release)
+            .process(&mut samples);
+    }
+
+    let mut sample_rate = SAMPLE_RATE as u32;
+    if let Some(target) = options.resample {
+        samples = resample::resample(&samples, sample_rate, target, options.interpolation);
+        sample_rate = target;
+    }
+    // last, so nothing after it can push the output back over the ceiling
+    if let Some(ceiling) = options.limit {
+        effects::Limiter::new(ceiling, options.limit_lookahead, options.limit_release, sample_rate)
+            .process(&mut samples);
+    }
+    (samples, sample_rate)
+}
+
+// Times the full render and post-processing `runs` times and reports the median
+fn benchmark(songs: &[Song], options: &Options, runs: usize) {
+    let mut times = Vec::with_capacity(runs);
+    let mut output = (Vec::new(), SAMPLE_RATE as u32);
+    for _ in 0..runs {
+        let start = Instant::now();
+        output = post_process(render_songs(songs, options), options);
+        times.push(start.elapsed().as_secs_f64());
+    }
+    times.sort_by(f64::total_cmp);
+    let median = times[runs / 2];
+
+    let (samples, sample_rate) = output;
+    let audio_seconds = samples.len() as f64 / sample_rate as f64;
+    println!("Runs: {}", runs);
+    println!("Median render time: {:.3} ms", median * 1000.0);
+    println!("Samples per second: {:.0}", samples.len() as f64 / median);
+    println!("Realtime ratio: {:.1}x ({:.2} s of audio)", audio_seconds / median, audio_seconds);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let options = match Options::parse(&args[1..]) {
+        Ok(options) => options,
+        Err(err) => {
+            if args.len() > 1 {
+                println!("{}\n", err);
+            }
+            println!("Available songs:");
+            for entry in std::fs::read_dir("songs").expect("Failed to read songs directory").flatten() {
+                println!("  {}", entry.file_name().to_string_lossy());
+            }
+            println!("\n{}", options::usage(&args[0]));
+            std::process::exit(1);
+        }
+    };
+
+    if options.info {
+        info::print(options.info_json);
+        return;
+    }
+    if let Some(name) = &options.save_preset {
+        match preset::expand(&args[1..]).and_then(|args| preset::save(name, &args)) {
+            Ok(path) => println!("Saved preset to {}", path),
+            Err(err) => {
+                println!("Failed to save preset: {}", err);
+                std::process::exit(1);
+            }
+        }
+        if options.songs.is_empty() {
+            return;
+        }
+    }
+    if let Some(address) = &options.listen {
+        if let Err(err) = live::run(address) {
+            println!("Live input stopped: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if options.lint {
+        let problems: usize = options.songs.iter().map(|name| lint::check(name, &read_song(name).notes)).sum();
+        if problems > 0 {
+            std::process::exit(1);
+        }
+        println!("No problems found");
+        return;
+    }
+
+    let mut rng = options.seed.map_or_else(Rng::from_time, Rng::new).with_audit(options.rng_audit);
+
+    let songs: Vec<Song> = options.songs.iter()
+        .map(|name| {
+            let mut song = load_song(name);
+            song.notes = unroll_loops(song.notes, &song.loops, note_gap(&options)).unwrap_or_else(|err| {
+                println!("{}: {}", name, err);
+                std::process::exit(1);
+            });
+            roll_probabilities(&mut song.notes, &mut rng);
+            roll_probabilities(&mut song.drone, &mut rng);
+            if options.from_note.is_some() || options.to_note.is_some() {
+                song.notes = slice_notes(song.notes, options.from_note, options.to_note).unwrap_or_else(|err| {
+                    println!("{}: {}", name, err);
+                    std::process::exit(1);
+                });
+            }
+            if options.legato {
+                song.notes = legato(song.notes);
+            }
+            // after legato, so the variations don't stop repeats from being tied
+            round_robin(&mut song.notes, &song.round_robin);
+            check_nyquist(name, "note", &mut song.notes, options.nyquist);
+            check_nyquist(name, "drone", &mut song.drone, options.nyquist);
+            song
+        })
+        .collect();
+    if options.rng_audit {
+        rng.print_summary();
+    } else if options.seed.is_none() && rng.draws() > 0 {
+        eprintln!("Seed: {}", rng.seed);
+    }
+
+    if options.info_per_note {
+        for (name, song) in options.songs.iter().zip(&songs) {
+            info::notes(name, &song.notes, &song.drone, &options);
+        }
+        return;
+    }
+
+    if options.suggest_tempo {
+        for (name, song) in options.songs.iter().zip(&songs) {
+            tempo::print(name, &song.notes);
+        }
+        return;
+    }
+
+    if options.filter_response {
+        let mut filters = Vec::new();
+        if options.autowah {
+            for cutoff in [options.wah_min, options.wah_max] {
+                filters.push((format!("wah {}Hz", cutoff), effects::Biquad::lowpass(cutoff, effects::AUTOWAH_Q)));
+            }
+        }
+
+        let mut cutoffs: Vec<f32> = Vec::new();
+        let mut vowels: Vec<effects::Vowel> = Vec::new();
+        for song in &songs {
+            for note in song.notes.iter().chain(&song.drone) {
+                let Resolved { brightness, cutoff, .. } = note.resolve(&options);
+                let stack = note.stack.iter().filter_map(|part| part.cutoff);
+                cutoffs.extend(cutoff.into_iter().chain(stack).map(|cutoff| cutoff * brightness));
+                vowels.extend(note.formant.filter(|vowel| !vowels.contains(vowel)));
+            }
+            let lanes = song.automation.iter()
+                .filter(|lane| lane.target == automation::Target::Cutoff)
+                .flat_map(|lane| lane.points.iter().map(|point| point[1]));
+            cutoffs.extend(lanes);
+        }
+        cutoffs.sort_by(f32::total_cmp);
+        cutoffs.dedup();
+        for cutoff in cutoffs {
+            filters.push((format!("lp {}Hz", cutoff), effects::Biquad::lowpass(cutoff, effects::BUTTERWORTH_Q)));
+        }
+        for vowel in vowels {
+            for (center, filter) in effects::Formant::bands(vowel) {
+                filters.push((format!("{} {}Hz", vowel.name(), center), filter));
+            }
+        }
+
+        if filters.is_empty() {
+            println!("No filters configured");
+        } else {
+            info::filter_response(&filters);
+        }
+        return;
+    }
+
+    if let Some(runs) = options.benchmark {
+        benchmark(&songs, &options, runs);
+        return;
+    }
+
+    if options.piano_roll {
+        roll::print(&onsets(&songs, &options), options.roll_width, options.roll_height);
+    }
+    if let Some(path) = &options.onsets {
+        let json = serde_json::to_string_pretty(&onsets(&songs, &options)).unwrap();
+        std::fs::write(path, json).expect("Failed to write onsets");
+        println!("Wrote onsets to {}", path);
+    }
+
+    let (samples, sample_rate) = post_process(render_songs(&songs, &options), &options);
+    if options.no_clip {
+        if let Some((i, peak)) = analysis::peak(&samples).filter(|(_, peak)| peak.abs() > 1.0) {
+            println!(
+                "Output clips: peak of {:.3} at {:.3}s, lower the level or use --limit",
+                peak, i as f32 / sample_rate as f32
+            );
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = &options.wav {
+        wav::write(path, &samples, sample_rate, options.bit_depth).expect("Failed to write WAV file");
+        println!("Wrote {}", path);
+    }
+    if let Some(path) = &options.waveform {
+        plot::waveform(&samples, path).expect("Failed to write waveform");
+        println!("Wrote waveform to {}", path);
+    }
+    if let Some(path) = &options.spectrogram {
+        plot::spectrogram(&samples, options.window, options.hop, path)
+            .expect("Failed to write spectrogram");
+        println!("Wrote spectrogram to {}", path);
+    }
+    if !options.assert_samples.is_empty() {
+        let mut failed = false;
+        for &(time, expected) in &options.assert_samples {
+            let actual = analysis::sample_at(&samples, sample_rate, time);
+            let ok = (actual - expected).abs() <= options.tolerance;
+            failed |= !ok;
+            println!("{} at {}s: expected {}, got {}", if ok { "ok  " } else { "FAIL" }, time, expected, actual);
+        }
+        if failed {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if options.exports() {
+        return;
+    }
 
+    println!("Playing: {}", options.songs.join(", "));
     let (_stream, output_stream_handle) = OutputStream::try_default().unwrap();
     let output_sink = Sink::try_new(&output_stream_handle).unwrap();
 
-    for note in notes {
-        let freq = note.frequency();
-        output_sink.append(SineWave::new(freq, note.duration));
-        output_sink.append(SineWave::new(0.0, 0.005));
+    output_sink.append(SamplesBuffer::new(1, sample_rate, samples));
+    output_sink.sleep_until_end();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(name: &str) -> Note {
+        Note { note: name.to_string(), ..Default::default() }
     }
 
-    output_sink.sleep_until_end();
+    #[test]
+    fn note_names_round_trip() {
+        for octave in 0..=8 {
+            for name in NOTE_NAMES {
+                let name = format!("{}{}", name, octave);
+                assert_eq!(frequency_to_note_name(note(&name).frequency()), name);
+            }
+        }
+    }
+
+    #[test]
+    fn halfway_between_semitones_rounds_up() {
+        let semitones = |n: f32| A4_FREQ * 2.0_f32.powf(n / OCTAVE_SEMITONES as f32);
+        assert_eq!(frequency_to_note_name(semitones(0.5)), "A#4");
+        assert_eq!(frequency_to_note_name(semitones(0.49)), "A4");
+        assert_eq!(frequency_to_note_name(semitones(-0.5)), "A4");
+        assert_eq!(frequency_to_note_name(semitones(-0.51)), "G#4");
+    }
+
+    #[test]
+    fn accidentals_and_lowercase_names() {
+        let frequency = |name: &str| note(name).frequency();
+        assert_eq!(frequency("cx4"), frequency("D4"));
+        assert_eq!(frequency("Cx4"), frequency("D4"));
+        assert_eq!(frequency("Dbb4"), frequency("C4"));
+        assert_eq!(frequency("dbb4"), frequency("C4"));
+        assert_eq!(frequency("c4"), frequency("C4"));
+        assert_eq!(frequency("f#3"), frequency("F#3"));
+        assert_eq!(frequency("bb5"), frequency("A#5"));
+        assert_eq!(frequency("a4"), A4_FREQ);
+    }
+
+    #[test]
+    fn octaves_below_zero_and_above_nine() {
+        let close = |a: f32, b: f32| (a / b - 1.0).abs() < 1e-5;
+        assert!(close(note("C-1").frequency(), note("C4").frequency() / 32.0));
+        assert!(close(note("G10").frequency(), note("G4").frequency() * 64.0));
+        assert_eq!(frequency_to_note_name(note("C-1").frequency()), "C-1");
+        assert_eq!(frequency_to_note_name(note("G10").frequency()), "G10");
+    }
+
+    #[test]
+    fn bad_names_are_errors() {
+        let names = ["", "H4", "Cq4", "C", "C#", "4", "40zhz", "0hz", "-5hz", "nanhz", "infhz"];
+        let octaves = ["C200000000", "C-200000000", "C-200", "C20000"];
+        for name in names.into_iter().chain(octaves) {
+            assert!(note(name).try_frequency().is_err(), "'{}' parsed", name);
+        }
+        for freq in [0.0, -440.0, f32::NAN, f32::INFINITY] {
+            assert!(Note { freq: Some(freq), ..note("") }.try_frequency().is_err(), "freq {} parsed", freq);
+        }
+    }
+
+    fn peak(samples: impl Iterator<Item = f32>) -> f32 {
+        samples.map(f32::abs).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn full_scale_oscillator_peaks_at_one() {
+        let peak_at = |amplitude: f32| peak(SineWave::new(A4_FREQ, 1.0).with_amplitude(amplitude));
+        assert!((peak_at(1.0) - 1.0).abs() < 1e-3);
+        assert!((peak(SineWave::new(A4_FREQ, 1.0)) - DEFAULT_AMPLITUDE).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sub_bass_starts_from_silence() {
+        let samples: Vec<f32> = SineWave::new(20.0, 1.0).with_phase(0.25).take(100).collect();
+        assert_eq!(samples[0], 0.0);
+        assert!(samples[1] < 1e-3);
+        assert!(samples.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn timing_does_not_drift() {
+        // a tenth of a second isn't exact in binary, so each duration is slightly off
+        let notes = vec![Note { duration: 0.1, ..note("C4") }; 5000];
+        let (spans, _) = schedule(&notes, 0.0);
+
+        for (i, span) in spans.iter().enumerate() {
+            assert_eq!(span.start, i * 4410);
+        }
+
+        // summing the same durations in f32 ends up more than a sample out
+        let naive: f32 = notes.iter().map(|note| note.duration).sum();
+        assert!((naive * SAMPLE_RATE - (notes.len() * 4410) as f32).abs() > 1.0);
+    }
+
+    #[test]
+    fn legato_ties_only_matching_sounds() {
+        let wavetable = |samples: Vec<f32>| Note { wavetable: Some(Wavetable::Samples(samples)), ..note("C4") };
+        let tied = legato(vec![note("C4"), note("C4"), wavetable(vec![1.0, -1.0]), note("E4")]);
+        assert_eq!(tied.len(), 3);
+    }
+
+    #[test]
+    fn nyquist_checks_stack_parts() {
+        let part = Part { wavetable: None, cutoff: None, tune_cents: 1200.0, gain: None };
+        let high = Note { freq: Some(15000.0), stack: vec![part], ..note("") };
+
+        let mut clamped = [high.clone()];
+        check_nyquist("test", "note", &mut clamped, Nyquist::Clamp);
+        let part = &clamped[0].stack[0];
+        assert!(15000.0 * 2.0_f32.powf(part.tune_cents / 1200.0) < SAMPLE_RATE / 2.0);
+
+        let mut skipped = [high];
+        check_nyquist("test", "note", &mut skipped, Nyquist::Skip);
+        assert!(skipped[0].stack.is_empty() && !skipped[0].is_rest());
+    }
+
+    #[test]
+    fn negative_duration_is_silent() {
+        let notes = [Note { duration: 1.0, ..note("C4") }, Note { duration: -0.5, ..note("E4") }, note("G4")];
+        let (spans, length) = schedule(&notes, 0.0);
+        assert_eq!(spans[1].length, 0);
+        assert_eq!(spans[2].start, SAMPLE_RATE as usize);
+        assert_eq!(length, SAMPLE_RATE as usize);
+    }
 }
@@ -0,0 +1,157 @@
+// LFOs routed to note parameters. A note (or, for notes without their own, the song) lists its
+// LFOs and then routes like {"source": "lfo1", "dest": "cutoff", "amount": 0.5}, where lfo1 is
+// the first LFO in the same list. Several routes to one destination add up. A note's own LFOs
+// start over with the note, the song's run from the start of the song so they can sweep across
+// many notes.
+//
+//   pitch:     amount in semitones
+//   amplitude: gain of 1 + amount * lfo, never below silence
+//   cutoff:    amount in octaves, for notes with a lowpass
+//   pan:       accepted, but the output is mono
+
+use serde::Deserialize;
+use std::f32::consts::PI;
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    #[default]
+    Sine,
+    Triangle,
+    Square,
+    Saw,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 4] = [Shape::Sine, Shape::Triangle, Shape::Square, Shape::Saw];
+
+    // Matches the serde name
+    pub fn name(self) -> &'static str {
+        match self {
+            Shape::Sine => "sine",
+            Shape::Triangle => "triangle",
+            Shape::Square => "square",
+            Shape::Saw => "saw",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct Lfo {
+    // Hz
+    pub rate: f32,
+    #[serde(default)]
+    pub shape: Shape,
+    // Peak of the output, 1 swings it from -1 to 1
+    pub depth: Option<f32>,
+}
+
+impl Lfo {
+    // Value `time` seconds after the LFO started
+    fn value(&self, time: f32) -> f32 {
+        let phase = (self.rate * time).fract();
+        let value = match self.shape {
+            Shape::Sine => (2.0 * PI * phase).sin(),
+            // starts at 0 rising, like the sine
+            Shape::Triangle => 1.0 - 4.0 * ((phase + 0.25).fract() - 0.5).abs(),
+            Shape::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Shape::Saw => 2.0 * phase - 1.0,
+        };
+        value * self.depth.unwrap_or(1.0)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Dest {
+    Pitch,
+    Amplitude,
+    Cutoff,
+    Pan,
+}
+
+impl Dest {
+    pub const ALL: [Dest; 4] = [Dest::Pitch, Dest::Amplitude, Dest::Cutoff, Dest::Pan];
+
+    // Matches the serde name
+    pub fn name(self) -> &'static str {
+        match self {
+            Dest::Pitch => "pitch",
+            Dest::Amplitude => "amplitude",
+            Dest::Cutoff => "cutoff",
+            Dest::Pan => "pan",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct Route {
+    pub source: String,
+    pub dest: Dest,
+    pub amount: f32,
+}
+
+impl Route {
+    // Index into the LFO list from a source named lfo1, lfo2, ...
+    fn lfo(&self) -> Option<usize> {
+        let index: usize = self.source.strip_prefix("lfo")?.parse().ok()?;
+        index.checked_sub(1)
+    }
+}
+
+// Checks every route names an LFO that exists
+pub fn validate(lfos: &[Lfo], routes: &[Route]) -> Result<(), String> {
+    match routes.iter().find(|route| route.lfo().is_none_or(|i| i >= lfos.len())) {
+        Some(route) => Err(format!("Modulation source '{}' isn't one of lfo1 to lfo{}", route.source, lfos.len())),
+        None => Ok(()),
+    }
+}
+
+pub struct Matrix<'a> {
+    lfos: &'a [Lfo],
+    routes: &'a [Route],
+}
+
+impl<'a> Matrix<'a> {
+    pub fn new(lfos: &'a [Lfo], routes: &'a [Route]) -> Self {
+        Self { lfos, routes }
+    }
+
+    pub fn targets(&self, dest: Dest) -> bool {
+        self.routes.iter().any(|route| route.dest == dest)
+    }
+
+    // Furthest the routes into `dest` can move it from zero, in either direction
+    pub fn peak(&self, dest: Dest) -> f32 {
+        self.routes
+            .iter()
+            .filter(|route| route.dest == dest)
+            .filter_map(|route| Some((route.amount * self.lfos.get(route.lfo()?)?.depth.unwrap_or(1.0)).abs()))
+            .sum()
+    }
+
+    // Sum of the routes into `dest` at `time` seconds after the LFOs started
+    pub fn value(&self, dest: Dest, time: f32) -> f32 {
+        self.routes
+            .iter()
+            .filter(|route| route.dest == dest)
+            .filter_map(|route| Some(route.amount * self.lfos.get(route.lfo()?)?.value(time)))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_start_at_zero_rising() {
+        for shape in [Shape::Sine, Shape::Triangle] {
+            let lfo = Lfo { rate: 1.0, shape, depth: None };
+            assert!(lfo.value(0.0).abs() < 1e-6);
+            assert!(lfo.value(0.1) > 0.0);
+            assert!((lfo.value(0.25) - 1.0).abs() < 1e-6);
+            assert!((lfo.value(0.75) + 1.0).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use crate::resample::Interpolation;
+use crate::wav::BitDepth;
+use crate::Nyquist;
+
+pub const USAGE: &str = "\
+Usage: {bin} [options] <song_name>...
+       {bin} --listen <address:port>
+       {bin} --info [json]
+
+Songs given together are played back to back. With --listen, notes are played live as
+UDP messages \"on <note> [velocity]\" and \"off <note>\" arrive. --info lists what this
+build supports.
+
+Options:
+";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Category {
+    Playback,
+    Synthesis,
+    Effect,
+    Output,
+}
+
+impl Category {
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Playback => "playback",
+            Category::Synthesis => "synthesis",
+            Category::Effect => "effect",
+            Category::Output => "output",
+        }
+    }
+}
+
+pub struct Flag {
+    pub name: &'static str,
+    pub value: &'static str,
+    pub help: &'static str,
+    pub category: Category,
+}
+
+const fn flag(name: &'static str, value: &'static str, category: Category, help: &'static str) -> Flag {
+    Flag { name, value, help, category }
+}
+
+// Options listed in the usage text and by --info
+pub const FLAGS: &[Flag] = &[
+    flag("--preset", "<name>", Category::Playback, "Apply the options saved in presets/<name>.json"),
+    flag("--save-preset", "<name>", Category::Playback, "Save this command's synthesis and effect options as a preset"),
+    flag("--gap", "<seconds>", Category::Playback, "Silence between consecutive songs (default 0)"),
+    flag("--tail", "<seconds>", Category::Playback, "Silence after the last note for effects to ring out (default 0)"),
+    flag("--seed", "<n>", Category::Playback, "Seed for everything random, printed when not given"),
+    flag("--rng-audit", "", Category::Playback, "Log every random draw and summarise them by feature"),
+    flag("--preview-seconds", "<n>", Category::Playback, "Only render the first n seconds"),
+    flag("--from-note", "<index>", Category::Playback, "Start at this note, counting from 0 after tuplets are expanded"),
+    flag("--to-note", "<index>", Category::Playback, "Stop after this note (inclusive)"),
+    flag("--continuous", "", Category::Synthesis, "Carry oscillator phase, lowpass state and note LFOs across notes instead of restarting them"),
+    flag("--legato", "", Category::Synthesis, "Tie consecutive notes of the same pitch unless marked articulate"),
+    flag("--high-precision", "", Category::Synthesis, "Run oscillator phase, filters and the note mix in f64, dynamics and flanger stay f32"),
+    flag("--normalize-per-note", "", Category::Synthesis, "Scale every note to the same RMS level"),
+    flag("--note-rms", "<level>", Category::Synthesis, "Target RMS for --normalize-per-note (default 0.25)"),
+    flag("--note-attack", "<ms>", Category::Synthesis, "Fade every note in over this long, replacing the gap between notes"),
+    flag("--note-release", "<ms>", Category::Synthesis, "Fade every note out over this long, replacing the gap between notes"),
+    flag("--nyquist", "<warn|clamp|skip>", Category::Synthesis, "Handling of notes above half the sample rate (default warn)"),
+    flag("--accent-gain", "<dB>", Category::Synthesis, "Boost for notes marked accent (default 3)"),
+    flag("--accent-brightness", "<n>", Category::Synthesis, "Multiplies an accented note's layer cutoff (default 1.5)"),
+    flag("--threshold", "<dB>", Category::Effect, "Compress the mix above this level"),
+    flag("--ratio", "<n>", Category::Effect, "Compression ratio (default 4)"),
+    flag("--attack", "<ms>", Category::Effect, "Compressor attack time (default 10)"),
+    flag("--release", "<ms>", Category::Effect, "Compressor release time (default 100)"),
+    flag("--autowah", "", Category::Effect, "Sweep a resonant lowpass with the signal level"),
+    flag("--wah-sensitivity", "<n>", Category::Effect, "How quickly the auto-wah opens with level (default 4)"),
+    flag("--wah-min", "<Hz>", Category::Effect, "Auto-wah cutoff when quiet (default 300)"),
+    flag("--wah-max", "<Hz>", Category::Effect, "Auto-wah cutoff when loud (default 3000)"),
+    flag("--flanger", "", Category::Effect, "Sweep a short delay against the dry signal"),
+    flag("--flanger-rate", "<Hz>", Category::Effect, "Flanger sweep rate (default 0.25)"),
+    flag("--flanger-depth", "<ms>", Category::Effect, "How far the flanger delay sweeps (default 3)"),
+    flag("--flanger-feedback", "<n>", Category::Effect, "Delayed signal fed back, -0.95 to 0.95 (default 0.5)"),
+    flag("--limit", "<dBFS>", Category::Effect, "Lookahead limit the output to this ceiling, e.g. -0.3"),
+    flag("--limit-lookahead", "<ms>", Category::Effect, "Limiter lookahead (default 5)"),
+    flag("--limit-release", "<ms>", Category::Effect, "Limiter release time (default 50)"),
+    flag("--benchmark", "", Category::Output, "Time the render without playing it"),
+    flag("--runs", "<n>", Category::Output, "Renders timed by --benchmark, the median is reported (default 5)"),
+    flag("--filter-response", "", Category::Output, "Print the frequency response of the configured filters"),
+    flag("--lint", "", Category::Output, "Check the songs for likely mistakes without playing them"),
+    flag("--info-per-note", "", Category::Output, "Print every note with the parameters it will be rendered with"),
+    flag("--suggest-tempo", "", Category::Output, "Print a likely tempo and each duration in beats"),
+    flag("--piano-roll", "", Category::Output, "Print an ASCII piano roll of the song before playing"),
+    flag("--roll-width", "<columns>", Category::Output, "Piano roll width (default 80)"),
+    flag("--roll-height", "<rows>", Category::Output, "Most rows the piano roll uses, pitches are grouped to fit (default 24)"),
+    flag("--onsets", "<file.json>", Category::Output, "Write the start time, duration and pitch of every note"),
+    flag("--no-clip", "", Category::Output, "Fail instead of producing output with samples beyond +/-1"),
+    flag("--wav", "<file.wav>", Category::Output, "Write the render to a WAV file instead of playing"),
+    flag("--bit-depth", "<16|24|32f>", Category::Output, "WAV sample format (default 16)"),
+    flag("--resample", "<Hz>", Category::Output, "Convert the render to this sample rate before output"),
+    flag("--interpolation", "<kind>", Category::Output, "Resampling method, linear or sinc (default sinc)"),
+    flag("--assert-sample", "<time>=<value>", Category::Output, "Render without playing and check the output there, repeatable"),
+    flag("--tolerance", "<n>", Category::Output, "Allowed difference for --assert-sample (default 0.001)"),
+    flag("--waveform", "<file.png>", Category::Output, "Write a plot of the amplitude envelope instead of playing"),
+    flag("--spectrogram", "<file.png>", Category::Output, "Write a spectrogram of the render instead of playing"),
+    flag("--window", "<samples>", Category::Output, "Spectrogram FFT window size, a power of two (default 1024)"),
+    flag("--hop", "<samples>", Category::Output, "Spectrogram hop between frames (default 256)"),
+];
+
+pub fn usage(bin: &str) -> String {
+    let mut usage = USAGE.replace("{bin}", bin);
+    for flag in FLAGS {
+        let signature = format!("{} {}", flag.name, flag.value);
+        usage.push_str(&format!("  {:<25}  {}\n", signature.trim_end(), flag.help));
+    }
+    usage
+}
+
+pub struct Options {
+    pub songs: Vec<String>,
+    pub listen: Option<String>,
+    pub info: bool,
+    pub info_json: bool,
+    pub save_preset: Option<String>,
+    pub gap: f32,
+    pub tail: f32,
+    pub seed: Option<u64>,
+    pub rng_audit: bool,
+    pub preview_seconds: Option<f32>,
+    pub from_note: Option<usize>,
+    pub to_note: Option<usize>,
+    pub continuous: bool,
+    pub legato: bool,
+    pub high_precision: bool,
+    pub normalize_per_note: Option<f32>,
+    pub note_attack: f32,
+    pub note_release: f32,
+    pub nyquist: Nyquist,
+    pub accent_gain: f32,
+    pub accent_brightness: f32,
+    pub threshold: Option<f32>,
+    pub ratio: f32,
+    pub attack: f32,
+    pub release: f32,
+    pub autowah: bool,
+    pub wah_sensitivity: f32,
+    pub wah_min: f32,
+    pub wah_max: f32,
+    pub flanger: bool,
+    pub flanger_rate: f32,
+    pub flanger_depth: f32,
+    pub flanger_feedback: f32,
+    pub limit: Option<f32>,
+    pub limit_lookahead: f32,
+    pub limit_release: f32,
+    pub benchmark: Option<usize>,
+    pub filter_response: bool,
+    pub lint: bool,
+    pub info_per_note: bool,
+    pub suggest_tempo: bool,
+    pub piano_roll: bool,
+    pub roll_width: usize,
+    pub roll_height: usize,
+    pub onsets: Option<String>,
+    pub no_clip: bool,
+    pub wav: Option<String>,
+    pub bit_depth: BitDepth,
+    pub resample: Option<u32>,
+    pub interpolation: Interpolation,
+    pub assert_samples: Vec<(f32, f32)>,
+    pub tolerance: f32,
+    pub waveform: Option<String>,
+    pub spectrogram: Option<String>,
+    pub window: usize,
+    pub hop: usize,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut songs = Vec::new();
+        let mut options = Options {
+            songs: Vec::new(),
+            listen: None,
+            info: false,
+            info_json: false,
+            save_preset: None,
+            gap: 0.0,
+            tail: 0.0,
+            seed: None,
+            rng_audit: false,
+            preview_seconds: None,
+            from_note: None,
+            to_note: None,
+            continuous: false,
+            legato: false,
+            high_precision: false,
+            normalize_per_note: None,
+            note_attack: 0.0,
+            note_release: 0.0,
+            nyquist: Nyquist::Warn,
+            accent_gain: 3.0,
+            accent_brightness: 1.5,
+            threshold: None,
+            ratio: 4.0,
+            attack: 10.0,
+            release: 100.0,
+            autowah: false,
+            wah_sensitivity: 4.0,
+            wah_min: 300.0,
+            wah_max: 3000.0,
+            flanger: false,
+            flanger_rate: 0.25,
+            flanger_depth: 3.0,
+            flanger_feedback: 0.5,
+            limit: None,
+            limit_lookahead: 5.0,
+            limit_release: 50.0,
+            benchmark: None,
+            filter_response: false,
+            lint: false,
+            info_per_note: false,
+            suggest_tempo: false,
+            piano_roll: false,
+            roll_width: 80,
+            roll_height: 24,
+            onsets: None,
+            no_clip: false,
+            wav: None,
+            bit_depth: BitDepth::Int16,
+            resample: None,
+            interpolation: Interpolation::Sinc,
+            assert_samples: Vec::new(),
+            tolerance: 0.001,
+            waveform: None,
+            spectrogram: None,
+            window: 1024,
+            hop: 256,
+        };
+
+        let mut normalize_per_note = false;
+        let mut note_rms = 0.25;
+
+        let mut benchmark = false;
+        let mut runs = 5;
+
+        let args = crate::preset::expand(args)?;
+        let mut args = args.iter().peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--info" => {
+                    options.info = true;
+                    options.info_json = args.next_if(|arg| *arg == "json").is_some();
+                }
+                "--listen" => options.listen = Some(value(arg, args.next())?),
+                "--save-preset" => options.save_preset = Some(value(arg, args.next())?),
+                "--gap" => options.gap = number(arg, args.next())?,
+                "--tail" => options.tail = number(arg, args.next())?,
+                "--seed" => options.seed = Some(number(arg, args.next())?),
+                "--rng-audit" => options.rng_audit = true,
+                "--preview-seconds" => options.preview_seconds = Some(number(arg, args.next())?),
+                "--from-note" => options.from_note = Some(number(arg, args.next())?),
+                "--to-note" => options.to_note = Some(number(arg, args.next())?),
+                "--continuous" => options.continuous = true,
+                "--legato" => options.legato = true,
+                "--high-precision" => options.high_precision = true,
+                "--normalize-per-note" => normalize_per_note = true,
+                "--note-rms" => note_rms = number(arg, args.next())?,
+                "--note-attack" => options.note_attack = number(arg, args.next())?,
+                "--note-release" => options.note_release = number(arg, args.next())?,
+                "--nyquist" => options.nyquist = value(arg, args.next())?.parse()?,
+                "--accent-gain" => options.accent_gain = number(arg, args.next())?,
+                "--accent-brightness" => options.accent_brightness = number(arg, args.next())?,
+                "--threshold" => options.threshold = Some(number(arg, args.next())?),
+                "--ratio" => options.ratio = number(arg, args.next())?,
+                "--attack" => options.attack = number(arg, args.next())?,
+                "--release" => options.release = number(arg, args.next())?,
+                "--autowah" => options.autowah = true,
+                "--wah-sensitivity" => options.wah_sensitivity = number(arg, args.next())?,
+                "--wah-min" => options.wah_min = number(arg, args.next())?,
+                "--wah-max" => options.wah_max = number(arg, args.next())?,
+                "--flanger" => options.flanger = true,
+                "--flanger-rate" => options.flanger_rate = number(arg, args.next())?,
+                "--flanger-depth" => options.flanger_depth = number(arg, args.next())?,
+                "--flanger-feedback" => options.flanger_feedback = number(arg, args.next())?,
+                "--limit" => options.limit = Some(number(arg, args.next())?),
+                "--limit-lookahead" => options.limit_lookahead = number(arg, args.next())?,
+                "--limit-release" => options.limit_release = number(arg, args.next())?,
+                "--benchmark" => benchmark = true,
+                "--runs" => runs = number(arg, args.next())?,
+                "--filter-response" => options.filter_response = true,
+                "--lint" => options.lint = true,
+                "--info-per-note" => options.info_per_note = true,
+                "--suggest-tempo" => options.suggest_tempo = true,
+                "--piano-roll" => options.piano_roll = true,
+                "--roll-width" => options.roll_width = number(arg, args.next())?,
+                "--roll-height" => options.roll_height = number(arg, args.next())?,
+                "--onsets" => options.onsets = Some(value(arg, args.next())?),
+                "--no-clip" => options.no_clip = true,
+                "--wav" => options.wav = Some(value(arg, args.next())?),
+                "--bit-depth" => options.bit_depth = value(arg, args.next())?.parse()?,
+                "--resample" => options.resample = Some(number(arg, args.next())?),
+                "--interpolation" => options.interpolation = value(arg, args.next())?.parse()?,
+                "--assert-sample" => {
+                    let raw = value(arg, args.next())?;
+                    let (time, expected) = raw
+                        .split_once('=')
+                        .ok_or(format!("{} expects <time>=<value>, got '{}'", arg, raw))?;
+                    options.assert_samples.push((
+                        number(arg, Some(&time.to_string()))?,
+                        number(arg, Some(&expected.to_string()))?,
+                    ));
+                }
+                "--tolerance" => options.tolerance = number(arg, args.next())?,
+                "--waveform" => options.waveform = Some(value(arg, args.next())?),
+                "--spectrogram" => options.spectrogram = Some(value(arg, args.next())?),
+                "--window" => options.window = number(arg, args.next())?,
+                "--hop" => options.hop = number(arg, args.next())?,
+                _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'", arg)),
+                _ => songs.push(arg.clone()),
+            }
+        }
+
+        if normalize_per_note {
+            options.normalize_per_note = Some(note_rms);
+        }
+        if benchmark {
+            if runs == 0 {
+                return Err("--runs must be at least 1".to_string());
+            }
+            options.benchmark = Some(runs);
+        }
+        if options.preview_seconds.is_some_and(|seconds| seconds <= 0.0) {
+            return Err("--preview-seconds must be greater than zero".to_string());
+        }
+        if options.roll_width < 8 || options.roll_height == 0 {
+            return Err("--roll-width must be at least 8 and --roll-height at least 1".to_string());
+        }
+        if options.ratio < 1.0 {
+            return Err(format!("--ratio must be at least 1, got {}", options.ratio));
+        }
+        if options.wah_min <= 0.0 || options.wah_max < options.wah_min {
+            return Err("--wah-min must be positive and no greater than --wah-max".to_string());
+        }
+        if options.resample == Some(0) {
+            return Err("--resample must be greater than zero".to_string());
+        }
+        if !options.window.is_power_of_two() {
+            return Err(format!("--window must be a power of two, got {}", options.window));
+        }
+        if options.hop == 0 {
+            return Err("--hop must be greater than zero".to_string());
+        }
+
+        if songs.is_empty()
+            && options.listen.is_none()
+            && !options.info
+            && !options.filter_response
+            && options.save_preset.is_none()
+        {
+            return Err("No song given".to_string());
+        }
+        options.songs = songs;
+        Ok(options)
+    }
+
+    // Whether the render is written to disk rather than played
+    pub fn exports(&self) -> bool {
+        self.wav.is_some() || self.waveform.is_some() || self.spectrogram.is_some()
+    }
+}
+
+fn value(flag: &str, value: Option<&String>) -> Result<String, String> {
+    value.cloned().ok_or(format!("{} expects a value", flag))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, raw: Option<&String>) -> Result<T, String> {
+    let raw = value(flag, raw)?;
+    raw.parse()
+        .map_err(|_| format!("{} expects a number, got '{}'", flag, raw))
+}
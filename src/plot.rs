@@ -0,0 +1,68 @@
+use crate::analysis::stft;
+use crate::png::Image;
+use std::io;
+
+const WAVEFORM_WIDTH: usize = 1200;
+const WAVEFORM_HEIGHT: usize = 300;
+const BACKGROUND: [u8; 3] = [16, 16, 24];
+const FOREGROUND: [u8; 3] = [90, 200, 250];
+// Magnitudes below this many dB under the loudest bin are drawn as black
+const DYNAMIC_RANGE_DB: f32 = 90.0;
+
+// Draws the min/max envelope of the samples, one column per block of samples.
+pub fn waveform(samples: &[f32], path: &str) -> io::Result<()> {
+    let mut image = Image::new(WAVEFORM_WIDTH, WAVEFORM_HEIGHT, BACKGROUND);
+    let per_column = samples.len().div_ceil(WAVEFORM_WIDTH).max(1);
+    let to_y = |v: f32| {
+        let v = v.clamp(-1.0, 1.0);
+        ((1.0 - v) * 0.5 * (WAVEFORM_HEIGHT - 1) as f32).round() as usize
+    };
+
+    for (x, block) in samples.chunks(per_column).enumerate() {
+        let min = block.iter().cloned().fold(f32::MAX, f32::min);
+        let max = block.iter().cloned().fold(f32::MIN, f32::max);
+        for y in to_y(max)..=to_y(min) {
+            image.set(x, y, FOREGROUND);
+        }
+    }
+
+    image.save(path)
+}
+
+// One column per analysis frame, low frequencies at the bottom.
+pub fn spectrogram(samples: &[f32], window: usize, hop: usize, path: &str) -> io::Result<()> {
+    let frames = stft(samples, window, hop);
+    let height = window / 2;
+    let mut image = Image::new(frames.len().max(1), height, [0, 0, 0]);
+
+    let loudest = frames
+        .iter()
+        .flatten()
+        .cloned()
+        .fold(f32::MIN_POSITIVE, f32::max);
+
+    for (x, frame) in frames.iter().enumerate() {
+        for (bin, magnitude) in frame.iter().enumerate() {
+            let db = 20.0 * (magnitude / loudest).max(1e-9).log10();
+            let level = ((db + DYNAMIC_RANGE_DB) / DYNAMIC_RANGE_DB).clamp(0.0, 1.0);
+            image.set(x, height - 1 - bin, heat(level));
+        }
+    }
+
+    image.save(path)
+}
+
+// black -> purple -> orange -> pale yellow
+const HEAT: [[f32; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [0.45, 0.05, 0.55],
+    [0.95, 0.45, 0.1],
+    [1.0, 1.0, 0.8],
+];
+
+fn heat(level: f32) -> [u8; 3] {
+    let scaled = level * (HEAT.len() - 1) as f32;
+    let i = (scaled as usize).min(HEAT.len() - 2);
+    let t = scaled - i as f32;
+    std::array::from_fn(|c| ((HEAT[i][c] + (HEAT[i + 1][c] - HEAT[i][c]) * t) * 255.0) as u8)
+}
@@ -0,0 +1,72 @@
+// Named sets of synthesis and effect options stored as presets/NAME.json, e.g.
+// {"flanger": true, "threshold": -12, "note-attack": 5}. --preset NAME stands in for those
+// flags at its place on the command line, so options after it override the preset and notes
+// still override both.
+
+use crate::options::{Category, FLAGS};
+use serde_json::{Map, Value};
+
+fn path(name: &str) -> String {
+    format!("presets/{}.json", name)
+}
+
+fn saved(category: Category) -> bool {
+    matches!(category, Category::Synthesis | Category::Effect)
+}
+
+// Replaces each --preset NAME with the flags it holds
+pub fn expand(args: &[String]) -> Result<Vec<String>, String> {
+    let mut expanded = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg != "--preset" {
+            expanded.push(arg.clone());
+            continue;
+        }
+
+        let name = args.next().ok_or("--preset expects a value")?;
+        let content = std::fs::read_to_string(path(name)).map_err(|_| format!("Preset '{}' not found", name))?;
+        let preset: Map<String, Value> =
+            serde_json::from_str(&content).map_err(|err| format!("Preset '{}': {}", name, err))?;
+        for (key, value) in preset {
+            let flag = format!("--{}", key);
+            if !FLAGS.iter().any(|known| known.name == flag && saved(known.category)) {
+                return Err(format!("Preset '{}': '{}' is not a synthesis or effect option", name, key));
+            }
+            match value {
+                Value::Bool(true) => expanded.push(flag),
+                Value::Bool(false) => {}
+                Value::String(value) => expanded.extend([flag, value]),
+                value => expanded.extend([flag, value.to_string()]),
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+// Writes the synthesis and effect options in `args` (already expanded) to presets/NAME.json
+pub fn save(name: &str, args: &[String]) -> Result<String, String> {
+    let mut preset = Map::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = FLAGS.iter().find(|flag| flag.name == arg) else {
+            continue;
+        };
+        let value = if flag.value.is_empty() {
+            Value::Bool(true)
+        } else {
+            let Some(value) = args.next() else {
+                continue;
+            };
+            value.parse().map_or(Value::String(value.clone()), |number: f64| number.into())
+        };
+        if saved(flag.category) {
+            preset.insert(flag.name.trim_start_matches("--").to_string(), value);
+        }
+    }
+
+    std::fs::create_dir_all("presets").map_err(|err| err.to_string())?;
+    let path = path(name);
+    std::fs::write(&path, serde_json::to_string_pretty(&preset).unwrap()).map_err(|err| err.to_string())?;
+    Ok(path)
+}
@@ -0,0 +1,89 @@
+// Sample rate conversion of a finished render.
+//
+// Linear interpolation is cheap but dulls the top octave and, when lowering the
+// rate, lets content above the new Nyquist limit fold back as aliasing. The
+// windowed sinc resampler low-passes at the lower of the two Nyquist limits and
+// keeps the passband flat, at roughly 2 * SINC_TAPS multiplies per output sample.
+
+use std::f32::consts::PI;
+
+// Input samples used on each side of the output position by the sinc resampler
+const SINC_TAPS: isize = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    Sinc,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 2] = [Interpolation::Linear, Interpolation::Sinc];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Interpolation::Linear => "linear",
+            Interpolation::Sinc => "sinc",
+        }
+    }
+}
+
+impl std::str::FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Interpolation::ALL
+            .into_iter()
+            .find(|interpolation| interpolation.name() == s)
+            .ok_or(format!("Unknown interpolation '{}', expected linear or sinc", s))
+    }
+}
+
+pub fn resample(samples: &[f32], from: u32, to: u32, interpolation: Interpolation) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let step = from as f64 / to as f64;
+    let length = (samples.len() as f64 / step).round() as usize;
+    let at = |i: isize| {
+        if i < 0 {
+            0.0
+        } else {
+            samples.get(i as usize).copied().unwrap_or(0.0)
+        }
+    };
+
+    (0..length)
+        .map(|n| {
+            let position = n as f64 * step;
+            let i = position.floor() as isize;
+            let fraction = (position - i as f64) as f32;
+
+            match interpolation {
+                Interpolation::Linear => at(i) + (at(i + 1) - at(i)) * fraction,
+                Interpolation::Sinc => {
+                    // scale the kernel down when lowering the rate so it filters below the new Nyquist
+                    let cutoff = (to as f32 / from as f32).min(1.0);
+                    (i - SINC_TAPS + 1..=i + SINC_TAPS)
+                        .map(|k| {
+                            let distance = (k - i) as f32 - fraction;
+                            at(k) * cutoff * sinc(distance * cutoff) * hann(distance)
+                        })
+                        .sum()
+                }
+            }
+        })
+        .collect()
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn hann(distance: f32) -> f32 {
+    0.5 + 0.5 * (PI * distance / SINC_TAPS as f32).cos()
+}
@@ -0,0 +1,62 @@
+// The one source of randomness for a render. Everything random draws from the same
+// generator in a fixed order, so a given --seed always produces the same output. Each draw
+// names the feature it is for, so --rng-audit can show where the randomness went.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// SplitMix64
+pub struct Rng {
+    pub seed: u64,
+    state: u64,
+    draws: BTreeMap<&'static str, usize>,
+    // Log every draw to stderr as it happens
+    audit: bool,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed, draws: BTreeMap::new(), audit: false }
+    }
+
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Self::new(seed)
+    }
+
+    pub fn with_audit(mut self, audit: bool) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    pub fn draws(&self) -> usize {
+        self.draws.values().sum()
+    }
+
+    pub fn next_u64(&mut self, feature: &'static str) -> u64 {
+        *self.draws.entry(feature).or_default() += 1;
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        if self.audit {
+            eprintln!("rng draw {} for {}: {:016x}", self.draws(), feature, z);
+        }
+        z
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f32(&mut self, feature: &'static str) -> f32 {
+        (self.next_u64(feature) >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn print_summary(&self) {
+        eprintln!("Seed {}, {} draws", self.seed, self.draws());
+        for (feature, count) in &self.draws {
+            eprintln!("  {:<12} {}", feature, count);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+// ASCII piano roll: one row per semitone (or group of semitones when the range is taller than
+// the requested height), time running left to right.
+
+use crate::{frequency_to_note_name, Onset, A4_FREQ, OCTAVE_SEMITONES};
+
+fn semitones_from_a4(frequency: f32) -> i32 {
+    (OCTAVE_SEMITONES as f32 * (frequency / A4_FREQ).log2()).round() as i32
+}
+
+pub fn print(onsets: &[Onset], width: usize, height: usize) {
+    let Some(end) = onsets.iter().map(|onset| onset.start + onset.duration).reduce(f32::max) else {
+        println!("(no notes)");
+        return;
+    };
+
+    let pitches: Vec<i32> = onsets.iter().map(|onset| semitones_from_a4(onset.frequency)).collect();
+    let lowest = *pitches.iter().min().unwrap();
+    let highest = *pitches.iter().max().unwrap();
+    let per_row = ((highest - lowest + 1) as usize).div_ceil(height.max(1)) as i32;
+    let rows = ((highest - lowest) / per_row + 1) as usize;
+    let seconds_per_column = end / width as f32;
+
+    let mut grid = vec![vec!['.'; width]; rows];
+    for (onset, pitch) in onsets.iter().zip(&pitches) {
+        let row = rows - 1 - ((pitch - lowest) / per_row) as usize;
+        let first = ((onset.start / seconds_per_column) as usize).min(width - 1);
+        let last = ((onset.start + onset.duration) / seconds_per_column).ceil() as usize;
+        for cell in &mut grid[row][first..last.clamp(first + 1, width)] {
+            *cell = '#';
+        }
+    }
+
+    for (i, row) in grid.iter().enumerate() {
+        let pitch = lowest + (rows - 1 - i) as i32 * per_row;
+        let label = frequency_to_note_name(A4_FREQ * 2.0_f32.powf(pitch as f32 / OCTAVE_SEMITONES as f32));
+        println!("{:>4} |{}", label, row.iter().collect::<String>());
+    }
+    println!("     +{}", "-".repeat(width));
+    println!("      0s{:>width$}", format!("{:.1}s", end), width = width - 2);
+}
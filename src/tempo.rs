@@ -0,0 +1,55 @@
+// Suggests a tempo for a song written in seconds, for --suggest-tempo. The beat unit is the
+// longest length every duration is a near whole multiple of, scaled by twos into a usual range.
+
+use crate::Note;
+use std::collections::BTreeMap;
+
+// Largest number of subdivisions of the shortest note tried as the unit
+const MAX_SUBDIVISIONS: u32 = 8;
+// How far from a whole multiple of the unit a duration may be, as a fraction of the unit
+const TOLERANCE: f32 = 0.05;
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 180.0;
+
+fn fits(durations: &[f32], unit: f32) -> bool {
+    durations.iter().all(|duration| {
+        let multiple = duration / unit;
+        (multiple - multiple.round()).abs() < TOLERANCE
+    })
+}
+
+pub fn print(name: &str, notes: &[Note]) {
+    // counted in milliseconds so equal durations group together
+    let mut counts: BTreeMap<u32, usize> = BTreeMap::new();
+    for note in notes.iter().filter(|note| note.duration > 0.0) {
+        *counts.entry((note.duration * 1000.0).round() as u32).or_default() += 1;
+    }
+    let durations: Vec<f32> = counts.keys().map(|&ms| ms as f32 / 1000.0).collect();
+    let Some(&shortest) = durations.first() else {
+        println!("{}: no durations to analyse", name);
+        return;
+    };
+
+    let Some(unit) = (1..=MAX_SUBDIVISIONS)
+        .map(|n| shortest / n as f32)
+        .find(|&unit| fits(&durations, unit))
+    else {
+        println!("{}: durations don't share a beat unit, no tempo to suggest", name);
+        return;
+    };
+
+    let mut beat = unit;
+    while 60.0 / beat > MAX_BPM {
+        beat *= 2.0;
+    }
+    while 60.0 / beat < MIN_BPM {
+        beat /= 2.0;
+    }
+
+    println!("{}: {:.1} BPM, one beat is {:.3}s", name, 60.0 / beat, beat);
+    println!("  {:>8}  {:>7}  {:>5}", "seconds", "beats", "notes");
+    for (duration, count) in durations.iter().zip(counts.values()) {
+        let beats = (duration / unit).round() * unit / beat;
+        println!("  {:>8.3}  {:>7.3}  {:>5}", duration, beats, count);
+    }
+}
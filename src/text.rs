@@ -0,0 +1,112 @@
+// Compact text songs, e.g. "bpm=120 time=4/4 C4:1 E4:1 G4:2 | R:1 C5:3". Each token is
+// note:beats, where the note is anything a JSON song accepts. A beat lasts a second unless
+// bpm= is given. | is a barline, checked against time= when it is given and otherwise only
+// there for reading.
+
+use crate::Note;
+
+// Tempos, beat counts and signatures are all lengths, so zero or less is a mistake
+fn positive(number: &str) -> Option<f32> {
+    number.parse().ok().filter(|number: &f32| number.is_finite() && *number > 0.0)
+}
+
+fn check_bar(bar: usize, beats: f32, beats_per_bar: Option<f32>) -> Result<(), String> {
+    match beats_per_bar {
+        Some(expected) if (beats - expected).abs() > 1e-3 => {
+            Err(format!("Bar {} has {} beats, the time signature needs {}", bar, beats, expected))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn parse(source: &str) -> Result<Vec<Note>, String> {
+    let mut seconds_per_beat = 1.0;
+    let mut beats_per_bar: Option<f32> = None;
+    let mut notes = Vec::new();
+    let mut bar = 1;
+    let mut bar_beats = 0.0;
+
+    for token in source.split_whitespace() {
+        if token == "|" {
+            check_bar(bar, bar_beats, beats_per_bar)?;
+            bar += 1;
+            bar_beats = 0.0;
+        } else if let Some(bpm) = token.strip_prefix("bpm=") {
+            let bpm = positive(bpm).ok_or(format!("Invalid tempo '{}', expected a positive number", token))?;
+            seconds_per_beat = 60.0 / bpm;
+        } else if let Some(signature) = token.strip_prefix("time=") {
+            // the note value isn't used, a beat is whatever bpm= counts, but it must still be one
+            let beats = signature
+                .split_once('/')
+                .filter(|(_, value)| value.parse::<u32>().is_ok_and(|value| value > 0))
+                .and_then(|(beats, _)| positive(beats));
+            beats_per_bar = Some(beats.ok_or(format!("Invalid time signature '{}'", token))?);
+        } else {
+            let (name, beats) = token
+                .rsplit_once(':')
+                .ok_or(format!("Expected note:beats, got '{}'", token))?;
+            let beats =
+                positive(beats).ok_or(format!("Invalid beats in '{}', expected a positive number", token))?;
+            bar_beats += beats;
+            notes.push(Note {
+                note: name.to_string(),
+                duration: beats * seconds_per_beat,
+                ..Default::default()
+            });
+        }
+    }
+    // a trailing barline leaves an empty bar that isn't one
+    if bar_beats > 0.0 {
+        check_bar(bar, bar_beats, beats_per_bar)?;
+    }
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn durations(source: &str) -> Vec<f32> {
+        parse(source).unwrap().iter().map(|note| note.duration).collect()
+    }
+
+    #[test]
+    fn beats_scale_with_bpm() {
+        assert_eq!(durations("C4:1 E4:2"), [1.0, 2.0]);
+        assert_eq!(durations("bpm=120 C4:1 E4:2 bpm=60 G4:1"), [0.5, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn bars_are_checked_against_the_time_signature() {
+        assert!(parse("time=4/4 C4:2 E4:2 | G4:4").is_ok());
+        assert!(parse("time=3/4 C4:2 E4:2 | G4:3").is_err());
+        // without a signature barlines are only for reading
+        assert!(parse("C4:2 | E4:1").is_ok());
+        // the last bar is checked even without a closing barline
+        assert!(parse("time=4/4 C4:4 | E4:1").is_err());
+    }
+
+    #[test]
+    fn trailing_barline_is_not_an_empty_bar() {
+        assert_eq!(durations("time=4/4 C4:4 |"), [4.0]);
+    }
+
+    #[test]
+    fn rejects_bad_tokens() {
+        for source in [
+            "bpm=0 C4:1",
+            "bpm=-60 C4:1",
+            "bpm=inf C4:1",
+            "bpm=fast C4:1",
+            "time=0/4 C4:1",
+            "time=4/0 C4:4",
+            "time=4/banana C4:4",
+            "time=4 C4:4",
+            "C4:0",
+            "C4:-1",
+            "C4",
+        ] {
+            assert!(parse(source).is_err(), "'{}' parsed", source);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// Mono WAV export. Integer formats clip samples outside [-1, 1], 32-bit float keeps them.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl BitDepth {
+    pub const ALL: [BitDepth; 3] = [BitDepth::Int16, BitDepth::Int24, BitDepth::Float32];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BitDepth::Int16 => "16",
+            BitDepth::Int24 => "24",
+            BitDepth::Float32 => "32f",
+        }
+    }
+}
+
+impl std::str::FromStr for BitDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        BitDepth::ALL
+            .into_iter()
+            .find(|depth| depth.name() == s)
+            .ok_or(format!("Unknown bit depth '{}', expected 16, 24 or 32f", s))
+    }
+}
+
+pub fn write(path: &str, samples: &[f32], sample_rate: u32, depth: BitDepth) -> Result<(), hound::Error> {
+    let (bits_per_sample, sample_format) = match depth {
+        BitDepth::Int16 => (16, hound::SampleFormat::Int),
+        BitDepth::Int24 => (24, hound::SampleFormat::Int),
+        BitDepth::Float32 => (32, hound::SampleFormat::Float),
+    };
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample,
+        sample_format,
+    };
+
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for &sample in samples {
+        match depth {
+            BitDepth::Int16 => writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?,
+            BitDepth::Int24 => writer.write_sample((sample.clamp(-1.0, 1.0) * 8_388_607.0) as i32)?,
+            BitDepth::Float32 => writer.write_sample(sample)?,
+        }
+    }
+    writer.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `samples` at `depth` and reads them back scaled to [-1, 1]
+    fn round_trip(samples: &[f32], depth: BitDepth) -> Vec<f32> {
+        let path = std::env::temp_dir().join(format!("synth_wav_test_{}.wav", depth.name()));
+        let path = path.to_str().unwrap();
+        write(path, samples, 44100, depth).unwrap();
+
+        let mut reader = hound::WavReader::open(path).unwrap();
+        let spec = reader.spec();
+        assert_eq!((spec.channels, spec.sample_rate), (1, 44100));
+        let read = match depth {
+            BitDepth::Int16 => reader.samples::<i16>().map(|s| s.unwrap() as f32 / i16::MAX as f32).collect(),
+            BitDepth::Int24 => reader.samples::<i32>().map(|s| s.unwrap() as f32 / 8_388_607.0).collect(),
+            BitDepth::Float32 => reader.samples::<f32>().map(|s| s.unwrap()).collect(),
+        };
+        std::fs::remove_file(path).unwrap();
+        read
+    }
+
+    const SAMPLES: [f32; 6] = [0.0, 0.5, -0.5, 0.999, -1.0, 0.123_456];
+
+    // Past full scale, which only the float format keeps
+    const OVERS: [f32; 2] = [1.5, -2.0];
+
+    #[test]
+    fn integer_formats_round_trip_within_a_step() {
+        for (depth, step) in [(BitDepth::Int16, 1.0 / 32767.0), (BitDepth::Int24, 1.0 / 8_388_607.0)] {
+            let read = round_trip(&[&SAMPLES[..], &OVERS].concat(), depth);
+            assert_eq!(read.len(), SAMPLES.len() + OVERS.len());
+            for (read, sample) in read.iter().zip(SAMPLES.iter().chain(&[1.0, -1.0])) {
+                assert!((read - sample).abs() <= step, "{} bit: {} read back as {}", depth.name(), sample, read);
+            }
+        }
+    }
+
+    #[test]
+    fn float_round_trips_exactly() {
+        let samples = [&SAMPLES[..], &OVERS].concat();
+        assert_eq!(round_trip(&samples, BitDepth::Float32), samples);
+    }
+}